@@ -0,0 +1,447 @@
+//! "The shell allows arithmetic expressions to be evaluated [...]
+//! Evaluation is done in fixed-width integers with no check for overflow, though division by 0 is trapped
+//! and flagged as an error. The operators and their precedence, associativity, and values are the same
+//! as in the C language."
+//!
+//! -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Shell-Arithmetic)
+
+use crate::variables::{VariableError, VARIABLES};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ArithmeticError {
+    #[error("{expression}: syntax error in expression (error token is \"{token}\")")]
+    Syntax { expression: String, token: String },
+    /// division by 0 or a negative exponent
+    #[error("{expression}: {message} (error token is \"{token}\")")]
+    Evaluation {
+        expression: String,
+        message: &'static str,
+        token: String,
+    },
+    #[error("{0}: value too great for base (error token is \"{0}\")")]
+    InvalidNumber(String),
+    #[error("{0}: expression recursion level exceeded")]
+    Recursion(String),
+    #[error(transparent)]
+    Variable(#[from] VariableError),
+}
+
+/// Evaluates `expression`, which has already been expanded.
+///
+/// Variables are read and assigned in the shell's [`VARIABLES`].
+pub fn evaluate(expression: &str) -> Result<i64, ArithmeticError> {
+    evaluate_nested(expression, 0)
+}
+
+/// values of variables are expressions themselves, `depth` guards against them referring to each other
+fn evaluate_nested(expression: &str, depth: usize) -> Result<i64, ArithmeticError> {
+    const MAX_DEPTH: usize = 1024;
+    if depth > MAX_DEPTH {
+        return Err(ArithmeticError::Recursion(expression.to_string()));
+    }
+
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = ExpressionParser {
+        expression,
+        tokens,
+        position: 0,
+    };
+    let tree = parser.comma()?;
+    if let Some(token) = parser.tokens.get(parser.position) {
+        return Err(parser.syntax_error(token));
+    }
+    tree.evaluate(expression, depth)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ArithmeticToken {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+/// longest first, so that an operator is never taken for its own prefix
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=",
+    "%=", "+=", "-=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~",
+    "?", ":", "=", ",", "(", ")",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<ArithmeticToken>, ArithmeticError> {
+    let mut tokens = Vec::new();
+    let mut rest = expression.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '#' || c == '@' || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(ArithmeticToken::Number(parse_number(&rest[..length])?));
+            rest = &rest[length..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            tokens.push(ArithmeticToken::Name(rest[..length].to_string()));
+            rest = &rest[length..];
+        } else if let Some(operator) = OPERATORS
+            .iter()
+            .find(|operator| rest.starts_with(*operator))
+        {
+            tokens.push(ArithmeticToken::Operator(operator));
+            rest = &rest[operator.len()..];
+        } else {
+            return Err(ArithmeticError::Syntax {
+                expression: expression.to_string(),
+                token: rest.to_string(),
+            });
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// decimal, `0x` hexadecimal, `0` octal or `base#digits` with a base from 2 to 64
+fn parse_number(number: &str) -> Result<i64, ArithmeticError> {
+    let invalid = || ArithmeticError::InvalidNumber(number.to_string());
+    let (base, digits) = if let Some((base, digits)) = number.split_once('#') {
+        let base = base.parse::<u32>().map_err(|_| invalid())?;
+        if !(2..=64).contains(&base) {
+            return Err(invalid());
+        }
+        (base, digits)
+    } else if let Some(digits) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        (16, digits)
+    } else if number.len() > 1 && number.starts_with('0') {
+        (8, &number[1..])
+    } else {
+        (10, number)
+    };
+    if digits.is_empty() {
+        return Err(invalid());
+    }
+
+    digits.chars().try_fold(0i64, |value, c| {
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => return Err(invalid()),
+        };
+        if digit >= base {
+            return Err(invalid());
+        }
+        Ok(value.wrapping_mul(base.into()).wrapping_add(digit.into()))
+    })
+}
+
+#[derive(Debug)]
+enum Expression {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expression>),
+    Binary(&'static str, Box<Expression>, Box<Expression>),
+    /// `=` or a compound assignment such as `+=`
+    Assign(&'static str, String, Box<Expression>),
+    /// `++name` or `--name`
+    PrefixStep(&'static str, String),
+    /// `name++` or `name--`
+    PostfixStep(&'static str, String),
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>),
+}
+
+struct ExpressionParser<'a> {
+    expression: &'a str,
+    tokens: Vec<ArithmeticToken>,
+    position: usize,
+}
+
+/// binary operators from the loosest binding to the tightest, below assignment and `?:`
+const BINARY_PRECEDENCE: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+const ASSIGNMENT_OPERATORS: [&str; 11] = [
+    "=", "*=", "/=", "%=", "+=", "-=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+impl ExpressionParser<'_> {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(ArithmeticToken::Operator(operator)) => Some(operator),
+            _ => None,
+        }
+    }
+
+    fn next_if_operator(&mut self, operators: &[&str]) -> Option<&'static str> {
+        let operator = self
+            .peek_operator()
+            .filter(|operator| operators.contains(operator))?;
+        self.position += 1;
+        Some(operator)
+    }
+
+    fn syntax_error(&self, token: &ArithmeticToken) -> ArithmeticError {
+        let token = match token {
+            ArithmeticToken::Number(number) => number.to_string(),
+            ArithmeticToken::Name(name) => name.clone(),
+            ArithmeticToken::Operator(operator) => (*operator).to_string(),
+        };
+        ArithmeticError::Syntax {
+            expression: self.expression.to_string(),
+            token,
+        }
+    }
+
+    fn unexpected(&self) -> ArithmeticError {
+        match self.tokens.get(self.position) {
+            Some(token) => self.syntax_error(token),
+            None => ArithmeticError::Syntax {
+                expression: self.expression.to_string(),
+                token: String::new(),
+            },
+        }
+    }
+
+    fn comma(&mut self) -> Result<Expression, ArithmeticError> {
+        let mut expression = self.assignment()?;
+        while self.next_if_operator(&[","]).is_some() {
+            expression =
+                Expression::Binary(",", Box::new(expression), Box::new(self.assignment()?));
+        }
+        Ok(expression)
+    }
+
+    fn assignment(&mut self) -> Result<Expression, ArithmeticError> {
+        if let (Some(ArithmeticToken::Name(name)), Some(ArithmeticToken::Operator(operator))) = (
+            self.tokens.get(self.position),
+            self.tokens.get(self.position + 1),
+        ) {
+            if ASSIGNMENT_OPERATORS.contains(operator) {
+                let (name, operator) = (name.clone(), *operator);
+                self.position += 2;
+                let value = self.assignment()?;
+                return Ok(Expression::Assign(operator, name, Box::new(value)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expression, ArithmeticError> {
+        let condition = self.binary(0)?;
+        if self.next_if_operator(&["?"]).is_none() {
+            return Ok(condition);
+        }
+        let if_true = self.comma()?;
+        if self.next_if_operator(&[":"]).is_none() {
+            return Err(self.unexpected());
+        }
+        let if_false = self.conditional()?;
+        Ok(Expression::Conditional(
+            Box::new(condition),
+            Box::new(if_true),
+            Box::new(if_false),
+        ))
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expression, ArithmeticError> {
+        let Some(operators) = BINARY_PRECEDENCE.get(level) else {
+            return self.power();
+        };
+        let mut expression = self.binary(level + 1)?;
+        while let Some(operator) = self.next_if_operator(operators) {
+            let right = self.binary(level + 1)?;
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+        Ok(expression)
+    }
+
+    /// `**` is right associative and binds tighter than the other binary operators
+    fn power(&mut self) -> Result<Expression, ArithmeticError> {
+        let base = self.unary()?;
+        if self.next_if_operator(&["**"]).is_some() {
+            let exponent = self.power()?;
+            return Ok(Expression::Binary("**", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn unary(&mut self) -> Result<Expression, ArithmeticError> {
+        if let Some(operator) = self.next_if_operator(&["++", "--"]) {
+            return match self.tokens.get(self.position) {
+                Some(ArithmeticToken::Name(name)) => {
+                    let name = name.clone();
+                    self.position += 1;
+                    Ok(Expression::PrefixStep(operator, name))
+                }
+                // `--1` is the negation of a negation
+                _ => {
+                    let sign = &operator[..1];
+                    let inner = Expression::Unary(sign, Box::new(self.unary()?));
+                    Ok(Expression::Unary(sign, Box::new(inner)))
+                }
+            };
+        }
+        if let Some(operator) = self.next_if_operator(&["!", "~", "+", "-"]) {
+            return Ok(Expression::Unary(operator, Box::new(self.unary()?)));
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expression, ArithmeticError> {
+        let Some(token) = self.tokens.get(self.position).cloned() else {
+            return Err(self.unexpected());
+        };
+        self.position += 1;
+        match token {
+            ArithmeticToken::Number(number) => Ok(Expression::Number(number)),
+            ArithmeticToken::Name(name) => match self.next_if_operator(&["++", "--"]) {
+                Some(operator) => Ok(Expression::PostfixStep(operator, name)),
+                None => Ok(Expression::Variable(name)),
+            },
+            ArithmeticToken::Operator("(") => {
+                let inner = self.comma()?;
+                if self.next_if_operator(&[")"]).is_none() {
+                    return Err(self.unexpected());
+                }
+                Ok(inner)
+            }
+            ArithmeticToken::Operator(_) => {
+                self.position -= 1;
+                Err(self.unexpected())
+            }
+        }
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, source: &str, depth: usize) -> Result<i64, ArithmeticError> {
+        let evaluate = |expression: &Self| expression.evaluate(source, depth);
+        Ok(match self {
+            Self::Number(number) => *number,
+            Self::Variable(name) => read_variable(name, depth)?,
+            Self::Unary(operator, operand) => {
+                let operand = evaluate(operand)?;
+                match *operator {
+                    "!" => (operand == 0).into(),
+                    "~" => !operand,
+                    "-" => operand.wrapping_neg(),
+                    _ => operand,
+                }
+            }
+            // evaluated lazily, as only one side may be needed
+            Self::Binary("&&", left, right) => {
+                (evaluate(left)? != 0 && evaluate(right)? != 0).into()
+            }
+            Self::Binary("||", left, right) => {
+                (evaluate(left)? != 0 || evaluate(right)? != 0).into()
+            }
+            Self::Binary(operator, left, right) => {
+                let left = evaluate(left)?;
+                let right = evaluate(right)?;
+                apply(operator, left, right).map_err(|message| ArithmeticError::Evaluation {
+                    expression: source.to_string(),
+                    message,
+                    token: right.to_string(),
+                })?
+            }
+            Self::Conditional(condition, if_true, if_false) => {
+                if evaluate(condition)? != 0 {
+                    evaluate(if_true)?
+                } else {
+                    evaluate(if_false)?
+                }
+            }
+            Self::Assign(operator, name, value) => {
+                let value = evaluate(value)?;
+                let value = match operator.strip_suffix('=').filter(|o| !o.is_empty()) {
+                    Some(operator) => {
+                        let current = read_variable(name, depth)?;
+                        apply(operator, current, value).map_err(|message| {
+                            ArithmeticError::Evaluation {
+                                expression: source.to_string(),
+                                message,
+                                token: value.to_string(),
+                            }
+                        })?
+                    }
+                    None => value,
+                };
+                VARIABLES.write().unwrap().set(name, value.to_string())?;
+                value
+            }
+            Self::PrefixStep(operator, name) | Self::PostfixStep(operator, name) => {
+                let current = read_variable(name, depth)?;
+                let stepped = if *operator == "++" {
+                    current.wrapping_add(1)
+                } else {
+                    current.wrapping_sub(1)
+                };
+                VARIABLES.write().unwrap().set(name, stepped.to_string())?;
+                if matches!(self, Self::PrefixStep(..)) {
+                    stepped
+                } else {
+                    current
+                }
+            }
+        })
+    }
+}
+
+/// `Err` with a message if the operation is undefined
+fn apply(operator: &str, left: i64, right: i64) -> Result<i64, &'static str> {
+    const DIVISION_BY_ZERO: &str = "division by 0";
+    Ok(match operator {
+        "," => right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "&" => left & right,
+        "==" => (left == right).into(),
+        "!=" => (left != right).into(),
+        "<" => (left < right).into(),
+        ">" => (left > right).into(),
+        "<=" => (left <= right).into(),
+        ">=" => (left >= right).into(),
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" if right == 0 => return Err(DIVISION_BY_ZERO),
+        "%" if right == 0 => return Err(DIVISION_BY_ZERO),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" => {
+            let exponent = u32::try_from(right).map_err(|_| "exponent less than 0")?;
+            left.wrapping_pow(exponent)
+        }
+        _ => unreachable!("`{operator}` is not a binary operator"),
+    })
+}
+
+/// an unset or empty variable is 0, otherwise its value is evaluated as an expression
+fn read_variable(name: &str, depth: usize) -> Result<i64, ArithmeticError> {
+    let value = VARIABLES.read().unwrap().get(name).map(str::to_string);
+    match value {
+        None => Ok(0),
+        Some(value) => evaluate_nested(&value, depth + 1),
+    }
+}
@@ -0,0 +1,290 @@
+//! The syntax tree produced by the [`Parser`][crate::parser::Parser].
+//!
+//! Nodes hold unexpanded [`Word`]s; expansion happens when the
+//! [`executor`][crate::executor] walks the tree.
+
+use crate::tokens::{RedirectOperator, Word};
+use itertools::Itertools;
+use std::fmt::{Display, Formatter};
+use std::iter::zip;
+use std::os::fd::RawFd;
+
+/// "A sequence of one or more pipelines separated by one of the operators ‘;’, ‘&’, ‘&&’, or ‘||’,
+/// and optionally terminated by one of ‘;’, ‘&’, or a newline."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Lists)
+#[derive(Debug, Clone)]
+pub struct List {
+    pub items: Vec<ListItem>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ListItem {
+    pub and_or: AndOrList,
+    /// `true` if terminated by `&`: the shell does not wait for it to finish
+    pub asynchronous: bool,
+}
+
+/// one or more [`Pipeline`]s joined by `&&` or `||`, evaluated left to right
+#[derive(Debug, Clone)]
+pub struct AndOrList {
+    pub head: Pipeline,
+    pub tail: Vec<(LogicalOperator, Pipeline)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LogicalOperator {
+    /// `&&`: run the following pipeline only if the previous one succeeded
+    And,
+    /// `||`: run the following pipeline only if the previous one failed
+    Or,
+}
+
+/// "A sequence of one or more commands separated by one of the control operators ‘|’ or ‘|&’."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Pipelines)
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    /// `true` if the pipeline is preceded by the reserved word `!`
+    pub negated: bool,
+    pub commands: Vec<Command>,
+    /// for each command, `true` if it is followed by `|&`: its stderr goes through the pipe as well
+    pub pipes_stderr: Vec<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// `(( expression ))`: succeeds if the arithmetic expression is not 0
+    Arithmetic(Word),
+    /// with the redirections that apply to the whole of it, as in `while read line; do ...; done < file`
+    Compound(CompoundCommand, Vec<Redirect>),
+}
+
+/// "Compound commands are the shell programming language constructs.
+/// Each construct begins with a reserved word or control operator and is terminated by a corresponding reserved word or operator."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Compound-Commands)
+#[derive(Debug, Clone)]
+pub enum CompoundCommand {
+    If(IfClause),
+    While(WhileLoop),
+    For(ForLoop),
+    ArithmeticFor(ArithmeticForLoop),
+}
+
+/// "The test-commands list is executed, and if its return status is zero, the consequent-commands list is executed.
+/// If test-commands returns a non-zero status, each elif list is executed in turn [...].
+/// If else alternate-consequents is present, and the final command in the final if or elif clause
+/// has a non-zero exit status, then alternate-consequents is executed."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-if)
+#[derive(Debug, Clone)]
+pub struct IfClause {
+    /// the `if` and `elif` conditions, each with the list run if it is the first to succeed
+    pub branches: Vec<(List, List)>,
+    pub else_branch: Option<List>,
+}
+
+/// `while condition; do body; done`, or `until condition; do body; done`
+#[derive(Debug, Clone)]
+pub struct WhileLoop {
+    /// `true` for an `until` loop, which runs its body as long as the condition fails
+    pub until: bool,
+    pub condition: List,
+    pub body: List,
+}
+
+/// `for name in words; do body; done`
+#[derive(Debug, Clone)]
+pub struct ForLoop {
+    pub name: String,
+    /// `None` without `in`: the loop goes over the positional parameters
+    pub words: Option<Vec<Word>>,
+    pub body: List,
+}
+
+/// "`for (( expr1 ; expr2 ; expr3 )) ; do commands ; done`
+///
+/// First, the arithmetic expression expr1 is evaluated [...].
+/// The arithmetic expression expr2 is then evaluated repeatedly until it evaluates to zero.
+/// Each time expr2 evaluates to a non-zero value, commands are executed and the arithmetic expression expr3 is evaluated.
+/// If any expression is omitted, it behaves as if it evaluates to 1."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-for)
+#[derive(Debug, Clone)]
+pub struct ArithmeticForLoop {
+    pub init: Word,
+    pub condition: Word,
+    pub update: Word,
+    pub body: List,
+}
+
+/// the unexpanded form of a [`SimpleCommand`][crate::commands::SimpleCommand]
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    /// `NAME=value` words preceding the command name.
+    /// Without a command name they set shell variables, otherwise only the command's environment
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    /// in the order they appeared, wherever that was among the words
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    /// the descriptor named before the operator, as in `2>`
+    pub io_number: Option<RawFd>,
+    pub operator: RedirectOperator,
+    /// the file, descriptor or here-string, or the delimiter of a here-document
+    pub target: Word,
+    /// the lines of a here-document, which follow the line the redirection is on
+    pub here_document: Option<Word>,
+}
+
+impl Redirect {
+    /// the file descriptor being redirected
+    pub fn fd(&self) -> RawFd {
+        self.io_number.unwrap_or_else(|| self.operator.default_fd())
+    }
+}
+
+impl Display for List {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.items.iter().format(" "))
+    }
+}
+
+impl Display for ListItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let terminator = if self.asynchronous { " &" } else { ";" };
+        write!(f, "{}{terminator}", self.and_or)
+    }
+}
+
+impl Display for AndOrList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.head)?;
+        for (operator, pipeline) in &self.tail {
+            let operator = match operator {
+                LogicalOperator::And => "&&",
+                LogicalOperator::Or => "||",
+            };
+            write!(f, " {operator} {pipeline}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Pipeline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.negated {
+            write!(f, "! ")?;
+        }
+        for (index, (command, pipes_stderr)) in zip(&self.commands, &self.pipes_stderr).enumerate()
+        {
+            if index > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{command}")?;
+            if index + 1 < self.commands.len() {
+                write!(f, " {}", if *pipes_stderr { "|&" } else { "|" })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Simple(simple_command) => write!(f, "{simple_command}"),
+            Self::Arithmetic(expression) => write!(f, "(({expression}))"),
+            Self::Compound(compound_command, redirects) => {
+                write!(f, "{compound_command}")?;
+                for redirect in redirects {
+                    write!(f, " {redirect}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for CompoundCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::If(if_clause) => write!(f, "{if_clause}"),
+            Self::While(WhileLoop {
+                until,
+                condition,
+                body,
+            }) => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{keyword} {condition} do {body} done")
+            }
+            Self::For(ForLoop { name, words, body }) => {
+                write!(f, "for {name}")?;
+                if let Some(words) = words {
+                    write!(f, " in {};", words.iter().format(" "))?;
+                }
+                write!(f, " do {body} done")
+            }
+            Self::ArithmeticFor(ArithmeticForLoop {
+                init,
+                condition,
+                update,
+                body,
+            }) => write!(f, "for (({init}; {condition}; {update})) do {body} done"),
+        }
+    }
+}
+
+impl Display for IfClause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, (condition, body)) in self.branches.iter().enumerate() {
+            let keyword = if index == 0 { "if" } else { "elif" };
+            write!(f, "{keyword} {condition} then {body} ")?;
+        }
+        if let Some(else_branch) = &self.else_branch {
+            write!(f, "else {else_branch} ")?;
+        }
+        write!(f, "fi")
+    }
+}
+
+impl Display for SimpleCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.assignments
+                .iter()
+                .map(ToString::to_string)
+                .chain(self.words.iter().map(ToString::to_string))
+                .chain(self.redirects.iter().map(ToString::to_string))
+                .format(" ")
+        )
+    }
+}
+
+impl Display for Redirect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(fd) = self.io_number {
+            write!(f, "{fd}")?;
+        }
+        write!(f, "{} {}", self.operator, self.target)
+    }
+}
+
+impl Display for Assignment {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)
+    }
+}
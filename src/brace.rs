@@ -0,0 +1,210 @@
+//! "Brace expansion is a mechanism by which arbitrary strings may be generated.
+//! [...] Patterns to be brace expanded take the form of an optional preamble, followed by either a series of
+//! comma-separated strings or a sequence expression between a pair of braces, followed by an optional postscript."
+//!
+//! -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Brace-Expansion)
+
+use crate::tokens::{Span, WordPart, WordPartKind};
+
+/// An unquoted character, which may take part in a brace expansion, or any other part of a word, which may not.
+#[derive(Debug, Clone)]
+enum Atom<'a> {
+    Char(char, Span),
+    Part(&'a WordPart),
+}
+
+impl Atom<'_> {
+    fn is_char(&self, c: char) -> bool {
+        matches!(self, Self::Char(atom, _) if *atom == c)
+    }
+}
+
+/// The words `parts` expands to, left to right.
+///
+/// Only unquoted braces take part; a word without a valid brace expression expands to itself.
+pub fn expand(parts: &[WordPart]) -> Vec<Vec<WordPart>> {
+    let atoms: Vec<Atom> = parts
+        .iter()
+        .flat_map(|part| match &part.kind {
+            WordPartKind::Literal(text) => text
+                .char_indices()
+                .map(|(index, c)| {
+                    let start = part.span.start + index;
+                    Atom::Char(c, start..start + c.len_utf8())
+                })
+                .collect(),
+            _ => vec![Atom::Part(part)],
+        })
+        .collect();
+    expand_atoms(&atoms).into_iter().map(into_parts).collect()
+}
+
+fn expand_atoms<'a>(atoms: &[Atom<'a>]) -> Vec<Vec<Atom<'a>>> {
+    let Some((open, close, alternatives)) = find_brace_expression(atoms) else {
+        return vec![atoms.to_vec()];
+    };
+    let preamble = &atoms[..open];
+    let postscripts = expand_atoms(&atoms[close + 1..]);
+
+    let mut words = Vec::new();
+    for alternative in alternatives {
+        for middle in expand_atoms(&alternative) {
+            for postscript in &postscripts {
+                words.push([preamble, &middle, postscript].concat());
+            }
+        }
+    }
+    words
+}
+
+/// The first `{` with a matching `}` that enclose a brace expression, with the alternatives it expands to.
+///
+/// A `{` that does not start one is left as it is.
+fn find_brace_expression<'a>(atoms: &[Atom<'a>]) -> Option<(usize, usize, Vec<Vec<Atom<'a>>>)> {
+    for open in (0..atoms.len()).filter(|&index| atoms[index].is_char('{')) {
+        let Some((close, commas)) = matching_brace(atoms, open) else {
+            continue;
+        };
+        let inner = &atoms[open + 1..close];
+        if !commas.is_empty() {
+            let mut alternatives = Vec::with_capacity(commas.len() + 1);
+            let mut start = open + 1;
+            for comma in commas.into_iter().chain([close]) {
+                alternatives.push(atoms[start..comma].to_vec());
+                start = comma + 1;
+            }
+            return Some((open, close, alternatives));
+        }
+        if let Some(sequence) = sequence(inner) {
+            let Atom::Char(_, Span { start, .. }) = atoms[open] else {
+                unreachable!("only a `{{` character starts a brace expression")
+            };
+            let Atom::Char(_, Span { end, .. }) = atoms[close] else {
+                unreachable!("only a `}}` character ends a brace expression")
+            };
+            let alternatives = sequence
+                .into_iter()
+                .map(|text| text.chars().map(|c| Atom::Char(c, start..end)).collect())
+                .collect();
+            return Some((open, close, alternatives));
+        }
+    }
+    None
+}
+
+/// the index of the `}` closing the `{` at `open`, with the indices of the commas separating its alternatives
+fn matching_brace(atoms: &[Atom], open: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (index, atom) in atoms.iter().enumerate().skip(open) {
+        match atom {
+            Atom::Char('{', _) => depth += 1,
+            Atom::Char('}', _) => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((index, commas));
+                }
+            }
+            Atom::Char(',', _) if depth == 1 => commas.push(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// "A sequence expression takes the form {x..y[..incr]}, where x and y are either integers or letters,
+/// and incr, an optional increment, is an integer."
+///
+/// `None` if `inner`, the text between the braces, is not one.
+fn sequence(inner: &[Atom]) -> Option<Vec<String>> {
+    let text = inner
+        .iter()
+        .map(|atom| match atom {
+            Atom::Char(c, _) => Some(*c),
+            Atom::Part(_) => None,
+        })
+        .collect::<Option<String>>()?;
+    let mut bounds = text.split("..");
+    let (first, last) = (bounds.next()?, bounds.next()?);
+    let increment = match bounds.next() {
+        Some(increment) => increment.parse::<i64>().ok()?,
+        None => 1,
+    };
+    if bounds.next().is_some() {
+        return None;
+    }
+
+    if let (Ok(first_number), Ok(last_number)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        // "When either x or y begins with a zero, the shell attempts to force all generated terms
+        // to contain the same number of digits, zero-padding where necessary."
+        let zero_padded =
+            |bound: &str| bound.trim_start_matches('-').starts_with('0') && bound.len() > 1;
+        let width = if zero_padded(first) || zero_padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        return Some(
+            steps(first_number, last_number, increment)
+                .map(|number| format!("{number:0width$}"))
+                .collect(),
+        );
+    }
+
+    let bound_char = |bound: &str| {
+        let mut chars = bound.chars();
+        chars
+            .next()
+            .filter(|c| c.is_ascii_alphabetic() && chars.next().is_none())
+    };
+    let (first_char, last_char) = (bound_char(first)?, bound_char(last)?);
+    Some(
+        steps(
+            u32::from(first_char).into(),
+            u32::from(last_char).into(),
+            increment,
+        )
+        .filter_map(|code| u8::try_from(code).ok())
+        .map(|code| char::from(code).to_string())
+        .collect(),
+    )
+}
+
+/// from `first` to `last` inclusive, in steps of the size of `increment`, counting down if `last` is smaller
+fn steps(first: i64, last: i64, increment: i64) -> impl Iterator<Item = i64> {
+    let step = increment.unsigned_abs().max(1);
+    let count = first.abs_diff(last) / step;
+    let down = last < first;
+    (0..=count).map(move |index| {
+        let offset = i64::try_from(index * step).unwrap_or(i64::MAX);
+        if down {
+            first - offset
+        } else {
+            first + offset
+        }
+    })
+}
+
+/// joins the characters of a word back into literal parts
+fn into_parts(atoms: Vec<Atom>) -> Vec<WordPart> {
+    let mut parts: Vec<WordPart> = Vec::new();
+    for atom in atoms {
+        match atom {
+            Atom::Char(c, span) => match parts.last_mut() {
+                Some(WordPart {
+                    kind: WordPartKind::Literal(text),
+                    span: previous,
+                }) => {
+                    text.push(c);
+                    previous.end = previous.end.max(span.end);
+                }
+                _ => parts.push(WordPart {
+                    kind: WordPartKind::Literal(c.to_string()),
+                    span,
+                }),
+            },
+            Atom::Part(part) => parts.push(part.clone()),
+        }
+    }
+    parts
+}
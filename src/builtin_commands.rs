@@ -1,14 +1,25 @@
-use crate::{executable_path::Executable, stream_target::OutStream, tokens::Token, EDITOR};
+use crate::{
+    arithmetic,
+    commands::{error_description, exit_code, exit_status_from_code},
+    executable_path::Executable,
+    executor,
+    jobs::{self, JOBS},
+    stream_target::{InStream, OutStream},
+    variables::{Variable, VariableError, VARIABLES},
+    EDITOR,
+};
 use itertools::Itertools;
 use my_derives::MyFromStrParse;
 use rustyline::{error::ReadlineError, history::History};
 use std::{
-    ffi::{OsStr, OsString},
+    ffi::OsStr,
     fmt::Debug,
     fs::File,
-    io::{self, read_to_string, BufRead, BufReader, Read, Seek, SeekFrom, Stderr, Stdout, Write},
+    io::{
+        self, read_to_string, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Stderr, Stdout,
+        Write,
+    },
     iter::zip,
-    os::unix::process::ExitStatusExt,
     path::{Path, PathBuf},
     process::ExitStatus,
 };
@@ -31,12 +42,73 @@ pub enum BuiltinCommand {
     ChangeDir,
     #[strum(serialize = "history")]
     History,
+    #[strum(serialize = "jobs")]
+    Jobs,
+    #[strum(serialize = "fg")]
+    Foreground,
+    #[strum(serialize = "bg")]
+    Background,
+    #[strum(serialize = "disown")]
+    Disown,
+    #[strum(serialize = "wait")]
+    Wait,
+    #[strum(serialize = "export")]
+    Export,
+    #[strum(serialize = "unset")]
+    Unset,
+    #[strum(serialize = "readonly")]
+    Readonly,
+    #[strum(serialize = "declare")]
+    Declare,
+    #[strum(serialize = "let")]
+    Let,
+    #[strum(serialize = "set")]
+    Set,
+    #[strum(serialize = "exec")]
+    Exec,
+    #[strum(serialize = "break")]
+    Break,
+    #[strum(serialize = "continue")]
+    Continue,
+    #[strum(serialize = "read")]
+    Read,
+}
+
+/// `exit` called in a subshell, which it ends rather than the shell, unwinding the commands up to it
+#[derive(Debug, thiserror::Error)]
+#[error("exit {0}")]
+pub struct ExitRequest(pub i32);
+
+/// `break` or `continue`, unwinding the execution of the commands up to the loop it applies to
+#[derive(Debug, thiserror::Error)]
+#[error("{} {}", if *.continues { "continue" } else { "break" }, .loops)]
+pub struct LoopControl {
+    /// `true` for `continue`: the loop goes on with its next iteration rather than ending
+    pub continues: bool,
+    /// how many of the enclosing loops it applies to, counting the innermost as 1
+    pub loops: usize,
+    pub exit_status: ExitStatus,
+}
+
+/// `result` with an [`ExitRequest`] turned into the status it asks for,
+/// for commands running apart from the shell that `exit` only ends
+pub fn catch_exit_request(result: io::Result<ExitStatus>) -> io::Result<ExitStatus> {
+    match result {
+        Err(e) => match e.get_ref().and_then(|e| e.downcast_ref::<ExitRequest>()) {
+            Some(ExitRequest(code)) => Ok(exit_status_from_code(*code)),
+            None => Err(e),
+        },
+        ok => ok,
+    }
 }
 
 impl BuiltinCommand {
+    /// Runs the builtin with `args`, reading from `in_reader` and writing to `out_writer` and `err_writer`,
+    /// which are the command's redirected or piped streams.
     pub(crate) fn run_with(
         &self,
-        args: &[Token],
+        args: &[String],
+        mut in_reader: InStream,
         mut out_writer: OutStream<Stdout>,
         mut err_writer: OutStream<Stderr>,
     ) -> io::Result<ExitStatus> {
@@ -44,20 +116,29 @@ impl BuiltinCommand {
 
         match self {
             Self::Exit => {
+                let exit_code = match args_iter.next() {
+                    None => exit_code(VARIABLES.read().unwrap().last_exit_status),
+                    Some(arg) => arg.parse::<i32>().unwrap_or_else(|_| {
+                        _ = writeln!(err_writer, "exit: {arg}: numeric argument required");
+                        2
+                    }),
+                };
+                if jobs::in_subshell() {
+                    // only the subshell ends, the error unwinds its execution
+                    return Err(io::Error::other(ExitRequest(exit_code)));
+                }
+
                 // write history then leave
-                let error_code = match Self::History.run_with(
-                    &[Token::from("-w".to_string())],
+                if let Err(error) = Self::History.run_with(
+                    &["-w".to_string()],
+                    InStream::Closed,
                     out_writer,
                     err_writer,
                 ) {
-                    Ok(status) => status,
-                    Err(error) => {
-                        log::error!("Error saving history on exit: {error}");
-                        todo!("use appropriate error code")
-                    }
-                };
+                    log::error!("Error saving history on exit: {error}");
+                }
 
-                std::process::exit(error_code.into_raw())
+                std::process::exit(exit_code & 0xff)
             }
             Self::Echo => {
                 writeln!(out_writer, "{}", args_iter.format(" "))?;
@@ -81,19 +162,18 @@ impl BuiltinCommand {
                 Ok(ExitStatus::default())
             }
             Self::ChangeDir => {
-                let mut path: PathBuf = args_iter.next().map_or_else(PathBuf::new, PathBuf::from);
-                let mut path_components = path.components();
-                if path_components.next()
-                    == Some(std::path::Component::Normal(&OsString::from("~")))
-                {
-                    let home: PathBuf = std::env::var("HOME").unwrap().into();
-                    path = {
-                        let mut builder = home;
-                        builder.extend(path_components);
-                        builder
-                    }
-                }
+                let path: PathBuf = match args_iter.next() {
+                    Some(arg) => PathBuf::from(arg),
+                    None => match VARIABLES.read().unwrap().get("HOME") {
+                        Some(home) => PathBuf::from(home),
+                        None => {
+                            writeln!(err_writer, "cd: HOME not set")?;
+                            return Ok(exit_status_from_code(1));
+                        }
+                    },
+                };
 
+                let old_dir = std::env::current_dir();
                 let cd_result = std::env::set_current_dir(&path);
 
                 if cd_result.is_err() {
@@ -102,8 +182,15 @@ impl BuiltinCommand {
                         "cd: {}: No such file or directory",
                         &path.to_string_lossy(),
                     )?;
-                    Ok(ExitStatus::from_raw(2))
+                    Ok(exit_status_from_code(2))
                 } else {
+                    let mut variables = VARIABLES.write().unwrap();
+                    for (name, dir) in [("OLDPWD", old_dir), ("PWD", std::env::current_dir())] {
+                        if let Ok(dir) = dir {
+                            // a readonly PWD is simply left alone
+                            let _ = variables.set(name, dir.to_string_lossy());
+                        }
+                    }
                     Ok(ExitStatus::default())
                 }
             }
@@ -189,26 +276,519 @@ impl BuiltinCommand {
                         });
 
                         let first_number = history.len().saturating_sub(size) + 1;
-                        for (num, item) in zip(first_number.., history.iter().tail(size)) {
-                            writeln!(out_writer, "{num:>5} {item}").unwrap(); // todo handle write error
-                        }
+                        let lines = zip(first_number.., history.iter().tail(size))
+                            .map(|(num, item)| format!("{num:>5} {item}"))
+                            .collect_vec();
+                        // the editor is not held while a full pipe blocks the writing
                         drop(e);
+                        for line in lines {
+                            writeln!(out_writer, "{line}").unwrap(); // todo handle write error
+                        }
                         Ok(ExitStatus::default())
                     }
                 }
             }
+            Self::Jobs => {
+                let mut only_pids = false;
+                let mut show_pids = false;
+                for arg in args_iter {
+                    match arg {
+                        "-p" => only_pids = true,
+                        "-l" => show_pids = true,
+                        invalid => {
+                            writeln!(err_writer, "jobs: {invalid}: invalid option")?;
+                            return Ok(exit_status_from_code(2));
+                        }
+                    }
+                }
+
+                jobs::report_finished_jobs(&mut out_writer)?;
+                let jobs = JOBS.lock().unwrap();
+                let lines = if only_pids {
+                    jobs.process_groups()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect()
+                } else {
+                    jobs.status_lines(show_pids)
+                };
+                drop(jobs);
+
+                for line in lines {
+                    writeln!(out_writer, "{line}")?;
+                }
+                Ok(ExitStatus::default())
+            }
+            Self::Foreground => {
+                let job = JOBS.lock().unwrap().take_for_foreground(args_iter.next());
+                match job {
+                    Ok(job) => {
+                        writeln!(out_writer, "{}", job.command)?;
+                        jobs::continue_in_foreground(job)
+                    }
+                    Err(message) => {
+                        writeln!(err_writer, "fg: {message}")?;
+                        Ok(exit_status_from_code(1))
+                    }
+                }
+            }
+            Self::Background => {
+                let continued = JOBS
+                    .lock()
+                    .unwrap()
+                    .continue_in_background(args_iter.next());
+                match continued {
+                    Ok(line) => {
+                        writeln!(out_writer, "{line}")?;
+                        Ok(ExitStatus::default())
+                    }
+                    Err(message) => {
+                        writeln!(err_writer, "bg: {message}")?;
+                        Ok(exit_status_from_code(1))
+                    }
+                }
+            }
+            Self::Disown => {
+                let mut jobs = JOBS.lock().unwrap();
+                let removed = match args_iter.next() {
+                    Some("-a") => {
+                        jobs.take_all();
+                        Ok(())
+                    }
+                    spec => jobs.take(spec).map(drop),
+                };
+                drop(jobs);
+
+                if let Err(message) = removed {
+                    writeln!(err_writer, "disown: {message}")?;
+                    return Ok(exit_status_from_code(1));
+                }
+                Ok(ExitStatus::default())
+            }
+            Self::Wait => {
+                if args_iter.peek().is_none() {
+                    let all_jobs = JOBS.lock().unwrap().take_all();
+                    for job in all_jobs {
+                        jobs::wait_for_job(job)?;
+                    }
+                    return Ok(ExitStatus::default());
+                }
+
+                let mut exit_status = ExitStatus::default();
+                for arg in args_iter {
+                    let job = if arg.starts_with('%') {
+                        JOBS.lock().unwrap().take(Some(arg))
+                    } else if let Ok(pid) = arg.parse() {
+                        JOBS.lock()
+                            .unwrap()
+                            .take_by_pid(pid)
+                            .ok_or_else(|| format!("pid {pid} is not a child of this shell"))
+                    } else {
+                        Err(format!("`{arg}': not a pid or valid job spec"))
+                    };
+
+                    exit_status = match job {
+                        Ok(job) => jobs::wait_for_job(job)?,
+                        Err(message) => {
+                            writeln!(err_writer, "wait: {message}")?;
+                            exit_status_from_code(127)
+                        }
+                    };
+                }
+                Ok(exit_status)
+            }
+            Self::Export | Self::Readonly => {
+                let (name, set_attribute): (_, fn(&mut Variable)) = match self {
+                    Self::Export => ("export", |variable| variable.exported = true),
+                    _ => ("readonly", |variable| variable.readonly = true),
+                };
+                if args_iter.next_if_eq(&"-p").is_some() || args_iter.peek().is_none() {
+                    let variables = VARIABLES.read().unwrap();
+                    let is_listed = |variable: &Variable| match self {
+                        Self::Export => variable.exported,
+                        _ => variable.readonly,
+                    };
+                    let declarations = variables
+                        .iter()
+                        .filter(|(_, v)| is_listed(v))
+                        .filter_map(|(name, _)| variables.declaration(name))
+                        .collect_vec();
+                    drop(variables);
+                    for declaration in declarations {
+                        writeln!(out_writer, "{declaration}")?;
+                    }
+                    return Ok(ExitStatus::default());
+                }
+                declare_all(name, args_iter, set_attribute, err_writer)
+            }
+            Self::Unset => {
+                args_iter.next_if_eq(&"-v");
+                let mut variables = VARIABLES.write().unwrap();
+                let errors = args_iter
+                    .filter_map(|name| match variables.unset(name) {
+                        Ok(()) => None,
+                        Err(VariableError::Readonly(_)) => {
+                            Some(format!("{name}: cannot unset: readonly variable"))
+                        }
+                        Err(e) => Some(e.to_string()),
+                    })
+                    .collect_vec();
+                drop(variables);
+                for error in &errors {
+                    writeln!(err_writer, "unset: {error}")?;
+                }
+                Ok(exit_status_from_code((!errors.is_empty()).into()))
+            }
+            Self::Declare => {
+                let mut print = false;
+                let mut export = false;
+                let mut readonly = false;
+                while let Some(flags) = args_iter.next_if(|arg| arg.starts_with('-')) {
+                    for flag in flags.chars().skip(1) {
+                        match flag {
+                            'p' => print = true,
+                            'x' => export = true,
+                            'r' => readonly = true,
+                            invalid => {
+                                writeln!(err_writer, "declare: -{invalid}: invalid option")?;
+                                return Ok(exit_status_from_code(2));
+                            }
+                        }
+                    }
+                }
+
+                if print || args_iter.peek().is_none() {
+                    let variables = VARIABLES.read().unwrap();
+                    let names: Vec<&str> = if args_iter.peek().is_none() {
+                        variables.iter().map(|(name, _)| name).collect()
+                    } else {
+                        args_iter.collect()
+                    };
+                    let declarations = names
+                        .into_iter()
+                        .map(|name| variables.declaration(name).ok_or_else(|| name.to_string()))
+                        .collect_vec();
+                    drop(variables);
+                    let mut exit_status = ExitStatus::default();
+                    for declaration in declarations {
+                        match declaration {
+                            Ok(declaration) => writeln!(out_writer, "{declaration}")?,
+                            Err(name) => {
+                                writeln!(err_writer, "declare: {name}: not found")?;
+                                exit_status = exit_status_from_code(1);
+                            }
+                        }
+                    }
+                    return Ok(exit_status);
+                }
+
+                declare_all(
+                    "declare",
+                    args_iter,
+                    |variable| {
+                        variable.exported |= export;
+                        variable.readonly |= readonly;
+                    },
+                    err_writer,
+                )
+            }
+            Self::Let => {
+                if args_iter.peek().is_none() {
+                    writeln!(err_writer, "let: expression expected")?;
+                    return Ok(exit_status_from_code(1));
+                }
+                let mut value = 0;
+                for expression in args_iter {
+                    match arithmetic::evaluate(expression) {
+                        Ok(result) => value = result,
+                        Err(e) => {
+                            writeln!(err_writer, "let: {e}")?;
+                            return Ok(exit_status_from_code(1));
+                        }
+                    }
+                }
+                // like `(( ))`, the status is 1 if the last expression was 0
+                Ok(exit_status_from_code((value == 0).into()))
+            }
+            Self::Set => {
+                if args_iter.peek().is_none() {
+                    let variables = VARIABLES.read().unwrap();
+                    let lines = variables
+                        .iter()
+                        .filter_map(|(name, variable)| {
+                            let value = variable.value.as_deref()?;
+                            Some(format!("{name}={}", single_quoted(value)))
+                        })
+                        .collect_vec();
+                    drop(variables);
+                    for line in lines {
+                        writeln!(out_writer, "{line}")?;
+                    }
+                    return Ok(ExitStatus::default());
+                }
+
+                let mut variables = VARIABLES.write().unwrap();
+                // written once the variables are released
+                let mut lines = Vec::new();
+                let mut options_ended = false;
+                while let Some(arg) = args_iter.next_if(|arg| arg.starts_with(['-', '+'])) {
+                    if arg == "-" || arg == "--" {
+                        options_ended = true;
+                        break;
+                    }
+                    // `-` turns an option on, `+` turns it off
+                    let (sign, flags) = arg.split_at(1);
+                    let enable = sign == "-";
+                    for flag in flags.chars() {
+                        match flag {
+                            'C' => variables.noclobber = enable,
+                            'o' => match args_iter.next() {
+                                Some("noclobber") => variables.noclobber = enable,
+                                Some(name) => {
+                                    drop(variables);
+                                    writeln!(err_writer, "set: {name}: invalid option name")?;
+                                    return Ok(exit_status_from_code(2));
+                                }
+                                None if enable => {
+                                    let state = if variables.noclobber { "on" } else { "off" };
+                                    lines.push(format!("noclobber      \t{state}"));
+                                }
+                                None => {
+                                    let sign = if variables.noclobber { '-' } else { '+' };
+                                    lines.push(format!("set {sign}o noclobber"));
+                                }
+                            },
+                            invalid => {
+                                drop(variables);
+                                writeln!(err_writer, "set: {sign}{invalid}: invalid option")?;
+                                return Ok(exit_status_from_code(2));
+                            }
+                        }
+                    }
+                }
+
+                let arguments: Vec<String> = args_iter.map(ToString::to_string).collect();
+                if options_ended || !arguments.is_empty() {
+                    variables.positional_parameters = arguments;
+                }
+                drop(variables);
+                for line in lines {
+                    writeln!(out_writer, "{line}")?;
+                }
+                Ok(ExitStatus::default())
+            }
+            // a command to run replaces the builtin before it gets here,
+            // and any redirections were already made permanent while preparing it
+            Self::Exec => Ok(ExitStatus::default()),
+            Self::Break | Self::Continue => {
+                let loop_depth = executor::loop_depth();
+                if loop_depth == 0 {
+                    writeln!(
+                        err_writer,
+                        "{self}: only meaningful in a `for', `while', or `until' loop"
+                    )?;
+                    return Ok(ExitStatus::default());
+                }
+                let mut control = LoopControl {
+                    continues: matches!(self, Self::Continue),
+                    loops: 1,
+                    exit_status: ExitStatus::default(),
+                };
+                if let Some(arg) = args_iter.next() {
+                    let error = match arg.parse::<i64>() {
+                        _ if args_iter.peek().is_some() => Some("too many arguments".to_string()),
+                        Ok(loops) if loops > 0 => {
+                            control.loops =
+                                usize::try_from(loops).unwrap_or(usize::MAX).min(loop_depth);
+                            None
+                        }
+                        Ok(_) => Some(format!("{arg}: loop count out of range")),
+                        Err(_) => Some(format!("{arg}: numeric argument required")),
+                    };
+                    if let Some(error) = error {
+                        // leaves every loop
+                        writeln!(err_writer, "{self}: {error}")?;
+                        control = LoopControl {
+                            continues: false,
+                            loops: loop_depth,
+                            exit_status: exit_status_from_code(1),
+                        };
+                    }
+                }
+                Err(io::Error::other(control))
+            }
+            Self::Read => {
+                let mut raw = false;
+                while let Some(option) = args_iter.next_if(|arg| arg.starts_with('-')) {
+                    match option {
+                        "-r" => raw = true,
+                        "-p" => {
+                            let Some(prompt) = args_iter.next() else {
+                                writeln!(err_writer, "read: -p: option requires an argument")?;
+                                return Ok(exit_status_from_code(2));
+                            };
+                            // only for input typed at a terminal
+                            if matches!(in_reader, InStream::Std) && io::stdin().is_terminal() {
+                                write!(err_writer, "{prompt}")?;
+                                err_writer.flush()?;
+                            }
+                        }
+                        "--" => break,
+                        _ => {
+                            writeln!(err_writer, "read: {option}: invalid option")?;
+                            return Ok(exit_status_from_code(2));
+                        }
+                    }
+                }
+
+                let (line, reached_end) = match read_line(&mut in_reader, raw) {
+                    Ok(read) => read,
+                    Err(e) => {
+                        writeln!(err_writer, "read: read error: 0: {}", error_description(&e))?;
+                        return Ok(exit_status_from_code(1));
+                    }
+                };
+                let names = args_iter.collect_vec();
+                let values = if names.is_empty() {
+                    vec![line.iter().map(|&(byte, _)| byte).collect()]
+                } else {
+                    split_line(&line, names.len())
+                };
+                let names = if names.is_empty() {
+                    vec!["REPLY"]
+                } else {
+                    names
+                };
+                let mut variables = VARIABLES.write().unwrap();
+                for (name, value) in zip(names, values) {
+                    if let Err(e) = variables.set(name, String::from_utf8_lossy(&value)) {
+                        writeln!(err_writer, "read: {e}")?;
+                        return Ok(exit_status_from_code(1));
+                    }
+                }
+                // a last line without a newline is still assigned
+                Ok(exit_status_from_code(reached_end.into()))
+            }
+        }
+    }
+}
+
+/// Reads up to a newline one byte at a time, so the commands that read after `read` get the rest of the input.
+///
+/// Each byte comes with whether a backslash quoted it, unless `raw` is set.
+/// `true` if the input ended before a newline.
+fn read_line(reader: &mut impl Read, raw: bool) -> io::Result<(Vec<(u8, bool)>, bool)> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+    let mut escaped = false;
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok((line, true));
+        }
+        match byte[0] {
+            // a backslash and newline continue the line
+            b'\n' if escaped => escaped = false,
+            b'\n' => return Ok((line, false)),
+            b'\\' if !raw && !escaped => escaped = true,
+            byte => {
+                line.push((byte, escaped));
+                escaped = false;
+            }
         }
     }
 }
 
+/// "The line is split into fields as with word splitting, and the first word is assigned to the first name,
+/// the second word to the second name, and so on, with any leftover words and their intervening delimiters
+/// assigned to the last name."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-read)
+fn split_line(line: &[(u8, bool)], count: usize) -> Vec<Vec<u8>> {
+    let ifs = VARIABLES
+        .read()
+        .unwrap()
+        .get("IFS")
+        .map_or_else(|| " \t\n".to_string(), str::to_string);
+    let is_separator = |&(byte, escaped): &(u8, bool)| !escaped && ifs.as_bytes().contains(&byte);
+    let is_whitespace = |entry: &(u8, bool)| is_separator(entry) && entry.0.is_ascii_whitespace();
+
+    let mut rest = line;
+    let mut values = Vec::with_capacity(count);
+    while let Some(start) = rest.iter().position(|entry| !is_whitespace(entry)) {
+        rest = &rest[start..];
+        if values.len() + 1 == count {
+            break;
+        }
+        let end = rest.iter().position(is_separator).unwrap_or(rest.len());
+        values.push(rest[..end].iter().map(|&(byte, _)| byte).collect());
+        rest = &rest[end..];
+        // whitespace around a separator is part of it
+        let whitespace = rest.iter().take_while(|entry| is_whitespace(entry)).count();
+        rest = &rest[whitespace..];
+        if rest.first().is_some_and(is_separator) {
+            rest = &rest[1..];
+        }
+    }
+    if values.len() < count {
+        let end = rest
+            .iter()
+            .rposition(|entry| !is_whitespace(entry))
+            .map_or(0, |end| end + 1);
+        values.push(rest[..end].iter().map(|&(byte, _)| byte).collect());
+    }
+    // the names left over are set to empty
+    values.resize(count, Vec::new());
+    values
+}
+
+/// `value` as a single word the shell reads back the same, quoted only if it needs to be
+fn single_quoted(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_./:-+,=@%".contains(c);
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_string();
+    }
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Declares every `NAME[=value]` in `args`, changing their attributes with `set_attribute`.
+///
+/// Invalid names and assignments to readonly variables are reported, the remaining arguments still apply.
+fn declare_all<'a>(
+    builtin: &str,
+    args: impl Iterator<Item = &'a str>,
+    set_attribute: impl Fn(&mut Variable),
+    mut err_writer: OutStream<Stderr>,
+) -> io::Result<ExitStatus> {
+    let mut variables = VARIABLES.write().unwrap();
+    let mut errors = Vec::new();
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg, None),
+        };
+        match variables.declare(name, value) {
+            Ok(variable) => set_attribute(variable),
+            Err(e) => errors.push(e),
+        }
+    }
+    // reported once the variables are released, as writing may block
+    drop(variables);
+    for error in &errors {
+        writeln!(err_writer, "{builtin}: {error}")?;
+    }
+    Ok(exit_status_from_code((!errors.is_empty()).into()))
+}
+
 pub fn history_default_path() -> Box<Path> {
     const HISTFILE_KEY: &str = "HISTFILE";
 
-    let path = std::env::var(HISTFILE_KEY)
+    let variables = VARIABLES.read().unwrap();
+    let path = variables
+        .get(HISTFILE_KEY)
         .map_or_else(
-            |_| {
+            || {
                 let backup_path = PathBuf::from_iter::<[&OsStr; 2]>([
-                    std::env::var_os("HOME").unwrap().as_ref(),
+                    variables.get("HOME").unwrap_or_default().as_ref(),
                     ".bash_history".as_ref(),
                 ]);
                 log::trace!(
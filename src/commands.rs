@@ -1,22 +1,55 @@
-use anyhow::anyhow;
-use std::io;
-use std::os::unix::process::ExitStatusExt;
+use nix::errno::Errno;
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::sys::signal::Signal;
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{close, dup2, dup3, fork, setpgid, ForkResult, Pid};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::iter::zip;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
 use std::path::Path;
 
-use crate::builtin_commands::BuiltinCommand;
-use crate::stream_target::{InStream, OutStream};
-use crate::tokens::Operator::{Control, Redirect};
-use crate::tokens::{is_shell_blank, ControlOperator, Operator, RedirectOperator, Token, Word};
-use std::fs::{File, OpenOptions};
-use std::io::{stderr, stdout, ErrorKind, Stderr, Stdout};
-use std::iter::Peekable;
+use crate::ast;
+use crate::builtin_commands::{catch_exit_request, BuiltinCommand, ExitRequest, LoopControl};
+use crate::executable_path::Executable;
+use crate::executor::Io;
+use crate::stream_target::{InStream, OutStream, SHELL_FDS};
+use crate::variables::VARIABLES;
+use std::io::{ErrorKind, Stderr, Stdout};
 use std::path::PathBuf;
 use std::process::ExitStatus;
-use std::str::Chars;
 
 #[derive(Debug)]
 pub struct Pipeline {
-    inner: Vec<SimpleCommand>,
+    pub inner: Vec<PipelineCommand>,
+    /// `None` unless job control is active
+    pub process_group: Option<ProcessGroup>,
+}
+
+/// how spawned processes take part in job control
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessGroup {
+    /// the group to join, or `None` for the process to lead a new group
+    pub id: Option<Pid>,
+    /// whether the group takes over the terminal
+    pub foreground: bool,
+}
+
+/// one of the commands of a [`Pipeline`]
+#[derive(Debug)]
+pub enum PipelineCommand {
+    Simple(SimpleCommand),
+    Shell(ShellCommand),
+}
+
+/// a command the shell evaluates itself in a subshell, such as a compound command
+#[derive(Debug)]
+pub struct ShellCommand {
+    pub command: ast::Command,
+    pub io: Io,
 }
 
 /// a sequence of [`Words`][`crate::tokens::Word`] separated by blanks, terminated by one of
@@ -26,327 +59,487 @@ pub struct Pipeline {
 #[derive(Debug)]
 pub struct SimpleCommand {
     pub location: CommandLocation,
-    pub args: Box<[Token]>,
+    pub args: Box<[String]>,
+    /// variables added to the environment of an external command, or assigned while a builtin runs
+    pub env: Vec<(String, String)>,
     pub stdin: InStream,
     pub stdout: OutStream<Stdout>,
     pub stderr: OutStream<Stderr>,
+    /// descriptors above 2 the command redirected, `None` for those it closed
+    pub fds: Vec<(RawFd, Option<File>)>,
+    /// true if the command runs in the shell itself rather than a subshell, so `exec` replaces the shell
+    pub in_shell_environment: bool,
+    /// `None` unless job control is active
+    pub process_group: Option<ProcessGroup>,
 }
 
-pub struct CommandStream<'a> {
-    token_stream: Peekable<TokenStream<'a>>,
+/// the [`ExitStatus`] of a process that exited normally with `code`
+pub fn exit_status_from_code(code: i32) -> ExitStatus {
+    ExitStatus::from_raw(code << 8)
 }
 
-#[derive(Clone, Debug)]
-struct TokenStream<'a> {
-    chars: Peekable<Chars<'a>>,
+/// the [`ExitStatus`] of a job that got stopped rather than finishing
+pub fn stopped_exit_status() -> ExitStatus {
+    ExitStatus::from_raw(0x7f | (Signal::SIGTSTP as i32) << 8)
 }
 
-/// Progresses the iterator until it reaches the `delimiter`.
-/// After returning, `iter` will have progressed passed the delimiter
-/// # Ok
-/// wraps the progressed slice (excluding the delimiter) in an `Ok`
-/// # Err
-/// wraps the progressed slice in an `Err` if end of iterator is reached
-fn build_quoted(iter: &mut Peekable<Chars>) -> Result<String, String> {
-    let original: String = iter.clone().collect();
-    let mut build = String::new();
-    let delimiter = iter.next().unwrap();
-
-    while let Some(char) = iter.next() {
-        match char {
-            _ if char == delimiter => return Ok(build),
-            '\\' if delimiter == '"' => {
-                build.push_str(proccess_escape_in_double_quote(iter).as_str());
-            }
+/// The number `$?` expands to: the exit code,
+/// or 128 plus the number of the signal that terminated or stopped the process.
+pub fn exit_code(exit_status: ExitStatus) -> i32 {
+    exit_status
+        .code()
+        .or_else(|| exit_status.signal().map(|signal| 128 + signal))
+        .or_else(|| exit_status.stopped_signal().map(|signal| 128 + signal))
+        .unwrap_or_default()
+}
 
-            _ => build.push(char),
-        }
+/// the message of an OS error, without the `(os error N)` std adds to it
+pub fn error_description(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.rfind(" (os error ") {
+        Some(end) => message[..end].to_string(),
+        None => message,
     }
-
-    Err(original)
 }
 
-fn proccess_escape_in_double_quote(iter: &mut Peekable<Chars>) -> String {
-    match iter.next() {
-        None => {
-            todo!("determine what to do when BACKSLASH is the last in the stream")
+/// Runs `run` in a forked copy of the shell, as each command of a pipeline runs in a subshell:
+/// whatever it changes, such as variables or the working directory, stays out of the shell itself.
+///
+/// `run` is only called in the child, which exits with the status it returns.
+/// Of the descriptors above 2 the shell has open, the child only keeps `kept_fds` and the [`SHELL_FDS`].
+pub fn spawn_subshell(
+    process_group: Option<ProcessGroup>,
+    kept_fds: Vec<RawFd>,
+    run: impl FnOnce() -> io::Result<ExitStatus>,
+) -> io::Result<ChildHandle> {
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+    // SAFETY: the child only runs `run` and exits. The shell runs on a single thread, evaluating
+    // anything that runs alongside it in a subshell like this one, so no other thread can be holding
+    // a lock `run` needs at the moment of the fork
+    match unsafe { fork() }? {
+        ForkResult::Parent { child } => {
+            if let Some(group) = process_group {
+                // done on both sides, so the group exists before the next command joins it
+                let _ = setpgid(child, group.id.unwrap_or(child));
+            }
+            Ok(ChildHandle::Subshell(child))
+        }
+        ForkResult::Child => {
+            if let Some(group) = process_group {
+                let _ = setpgid(Pid::from_raw(0), group.id.unwrap_or(Pid::from_raw(0)));
+                let _ = crate::jobs::prepare_job_process(group.foreground);
+            }
+            crate::jobs::enter_subshell();
+            if let Err(e) = release_other_fds(kept_fds) {
+                log::warn!("failed to release the descriptors of the shell in a subshell: {e}");
+            }
+            let result = catch_exit_request(run());
+            let exit_status = result.unwrap_or_else(|e| {
+                // `break` and `continue` only end the subshell
+                match e.get_ref().and_then(|e| e.downcast_ref::<LoopControl>()) {
+                    Some(control) => control.exit_status,
+                    None => {
+                        eprintln!("{e}");
+                        exit_status_from_code(1)
+                    }
+                }
+            });
+            let _ = io::stdout().flush();
+            std::process::exit(exit_code(exit_status))
         }
-        Some(c @ ('$' | '\\' | '"')) => c.into(),
-        Some(c) => ['\\', c].iter().collect(),
     }
 }
 
-pub trait Command {
-    fn run_blocking(self) -> io::Result<ExitStatus>
-    where
-        Self: Sized,
-    {
-        self.spawn()?.wait()
+/// Lets go of every descriptor above 2 but `kept_fds` and the [`SHELL_FDS`], in a freshly forked subshell.
+///
+/// The shell may be holding the reading end of a pipe for a command it has yet to spawn, for example:
+/// left open in the subshell, it would keep the writer from ever seeing its reader go away.
+/// Each descriptor is pointed at `/dev/null` rather than closed, as whatever owns it may still close it.
+fn release_other_fds(mut kept_fds: Vec<RawFd>) -> io::Result<()> {
+    kept_fds.extend(SHELL_FDS.lock().unwrap().values().map(AsRawFd::as_raw_fd));
+    let open_fds: Vec<RawFd> = std::fs::read_dir("/dev/fd")?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+    let null = File::open("/dev/null")?;
+    for fd in open_fds {
+        let released = fd > STDERR_FILENO && fd != null.as_raw_fd() && !kept_fds.contains(&fd);
+        // the descriptor that listed the directory is closed by now
+        if released && fcntl(fd, FcntlArg::F_GETFD).is_ok() {
+            dup3(null.as_raw_fd(), fd, OFlag::O_CLOEXEC)?;
+        }
     }
+    Ok(())
+}
 
+pub trait Command {
     fn spawn(self) -> io::Result<ChildHandle>;
 }
 
-impl Iterator for TokenStream<'_> {
-    type Item = Token;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut token_builder = String::new();
-
-        while let Some(peeked_char) = self.chars.peek() {
-            match peeked_char {
-                w if is_shell_blank(w) => {
-                    self.chars.next(); // consume the blank
-                    if !token_builder.is_empty() {
-                        break;
-                    }
+impl Command for Pipeline {
+    fn spawn(self) -> io::Result<ChildHandle> {
+        let mut children = Vec::with_capacity(self.inner.len());
+        let mut process_group = self.process_group;
+        let concurrent = self.inner.len() > 1;
+        for command in self.inner {
+            let child = match command {
+                PipelineCommand::Simple(mut command)
+                    if !concurrent || matches!(command.location, CommandLocation::External(_)) =>
+                {
+                    command.process_group = process_group;
+                    command.spawn()?
                 }
-                meta_c if Operator::may_start_with(meta_c.to_string().as_str()) => {
-                    match try_build_operator(&self.chars) {
-                        Ok(operator) => {
-                            if !token_builder.is_empty() {
-                                break;
-                            }
-
-                            token_builder = operator;
-                            for _ in token_builder.chars() {
-                                self.chars.next();
-                            } // todo replace for loop with returned clone instead? (requires converting while into a loop)
-                            break;
+                // run alongside the other commands, which may need to read what it writes to finish
+                in_shell => {
+                    // not the pipes of the following commands, such as the reading end of the one it writes to:
+                    // the reader going away must be what ends its writing
+                    let kept_fds = match &in_shell {
+                        PipelineCommand::Simple(command) => command.raw_fds(),
+                        PipelineCommand::Shell(command) => command.io.raw_fds(),
+                    };
+                    spawn_subshell(process_group, kept_fds, || match in_shell {
+                        PipelineCommand::Simple(command) => {
+                            command.spawn().and_then(|mut child| child.wait())
                         }
-                        Err(()) => {
-                            // consume peeked value
-                            token_builder
-                                .push(self.chars.next().expect("peeked to confirm is some"));
+                        PipelineCommand::Shell(ShellCommand { command, io }) => {
+                            command.execute(&io)
                         }
-                    }
-                }
-                '\\' => {
-                    _ = self.chars.next();
-                    if let Some(following) = self.chars.next() {
-                        token_builder.push(following);
-                    }
+                    })?
                 }
-                '"' | '\'' => match build_quoted(&mut self.chars) {
-                    Ok(s) => token_builder.push_str(&s),
-                    Err(ending) => token_builder.push_str(&ending),
-                },
-                _ => token_builder.push(self.chars.next().expect("peeked to confirm is some")),
+            };
+            if let Some(group) = process_group.as_mut() {
+                // the first process to be spawned leads the group of the whole pipeline
+                group.id = group.id.or_else(|| child.process_group());
             }
-        }
-        if token_builder.is_empty() {
-            return None;
-        }
-        Some(token_builder.into())
-    }
-}
-
-// todo fix this mess
-fn try_build_operator(iter: &Peekable<Chars>) -> Result<String, ()> {
-    let mut iter_clone = iter.clone();
-    let mut buf = String::new();
-
-    while let Some(c) = iter_clone.next_if(|c| {
-        let potential = format!("{buf}{c}");
-        Operator::may_start_with(&potential)
-    }) {
-        buf.push(c);
-    }
-    match buf.parse::<Operator>() {
-        Ok(_) => Ok(buf),
-        Err(()) => Err(()),
-    }
-}
-
-impl Command for Pipeline {
-    fn run_blocking(self) -> io::Result<ExitStatus> {
-        let mut exit_status = ExitStatus::default();
-
-        // spawn all
-        let mut children = Vec::with_capacity(self.inner.len());
-        for command in self.inner {
-            let child = command.spawn()?;
             children.push(child);
         }
-
-        // wait on all
-        for mut child_process in children {
-            exit_status = child_process.wait()?;
-        }
-        Ok(exit_status)
-    }
-
-    fn spawn(self) -> io::Result<ChildHandle> {
-        todo!()
+        Ok(ChildHandle::Pipeline(children))
     }
 }
 
-impl Iterator for CommandStream<'_> {
-    type Item = Result<Pipeline, anyhow::Error>;
-
-    fn next(&mut self) -> Option<Result<Pipeline, anyhow::Error>> {
-        use ControlOperator::{Newline, Pipe, PipeAmp};
-        use Token::Operator;
-
-        let mut command_pipeline = Vec::new();
-
-        let mut following_reader = None;
-
-        while let Some(first_token) = self.token_stream.next() {
-            let stdin = following_reader.take().unwrap_or(InStream::Std);
-            let (mut stdout, mut stderr) = (OutStream::Std(stdout()), OutStream::Std(stderr()));
-
-            let location = match CommandLocation::try_from(first_token) {
-                Ok(location) => location,
-                Err(e) => return Some(Err(e)),
-            };
-
-            let mut args = Vec::new();
+impl Command for SimpleCommand {
+    fn spawn(self) -> io::Result<ChildHandle> {
+        if let CommandLocation::Builtin(BuiltinCommand::Exec) = self.location {
+            if let Some((name, args)) = self.args.split_first() {
+                // `exec` looks only for external commands
+                let command = Self {
+                    location: CommandLocation::External(PathBuf::from(name).into_boxed_path()),
+                    args: args.into(),
+                    ..self
+                };
+                return if command.in_shell_environment {
+                    command.replace_shell()
+                } else {
+                    command.spawn()
+                };
+            }
+        }
 
-            'command: loop {
-                let Some(token) = self.token_stream.next() else {
-                    break 'command;
+        match self.location {
+            CommandLocation::Builtin(bltn_command) => {
+                let assigned = VARIABLES.write().unwrap().assign_temporarily(&self.env);
+                let saved = match assigned {
+                    Ok(saved) => saved,
+                    Err(e) => {
+                        eprintln!("{e}");
+                        return Ok(ChildHandle::Completed(exit_status_from_code(1)));
+                    }
                 };
-                match token {
-                    Operator(Control(Newline)) => break 'command,
-                    Operator(Redirect(redir)) => {
-                        use RedirectOperator as R;
-
-                        // add redirect
-                        let Some(Ok(path_buf)) = self.token_stream.next().map(PathBuf::try_from)
-                        else {
-                            return Some(Err(anyhow!("expected a path after redirect operator")));
-                        };
-
-                        let file: File = {
-                            match OpenOptions::new()
-                                .create(true)
-                                .read(true)
-                                .write(true)
-                                .append(redir.appends())
-                                .open(&path_buf)
-                            {
-                                Ok(f) => f,
-                                Err(io_error) => return Some(Err(io_error.into())),
+                let result =
+                    bltn_command.run_with(&self.args, self.stdin, self.stdout, self.stderr);
+                VARIABLES.write().unwrap().restore(saved);
+                match result {
+                    Ok(exit_status) => Ok(ChildHandle::Completed(exit_status)),
+                    // unwinds the commands up to what it ends
+                    Err(e)
+                        if e.get_ref()
+                            .is_some_and(|e| e.is::<ExitRequest>() || e.is::<LoopControl>()) =>
+                    {
+                        Err(e)
+                    }
+                    // the reader went away, as a process would be killed by SIGPIPE
+                    Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(ChildHandle::Completed(
+                        exit_status_from_code(128 + Signal::SIGPIPE as i32),
+                    )),
+                    Err(e) => {
+                        // such as writing to a closed descriptor
+                        match e.raw_os_error() {
+                            Some(_) => {
+                                eprintln!("{bltn_command}: write error: {}", error_description(&e))
                             }
-                        };
-
-                        match redir {
-                            R::RStdin => todo!("handle [ file < command ]"), // todo error if command_vec.len() > 0 ???
-                            R::RStdout | R::AppendStdout => stdout = OutStream::File(file),
-                            R::RStderr | R::AppendStderr => stderr = OutStream::File(file),
+                            None => eprintln!("{bltn_command}: {e}"),
                         }
-
-                        break 'command;
+                        Ok(ChildHandle::Completed(exit_status_from_code(1)))
                     }
-                    Operator(Control(Pipe)) => {
-                        let (reader, writer) = crate::stream_target::pipe();
-                        stdout = OutStream::PipeWriter(writer);
-                        following_reader = Some(InStream::PipeReader(reader));
-
-                        break 'command;
+                }
+            }
+            CommandLocation::External(ref external) => {
+                let name = external.to_string_lossy().into_owned();
+                let (mut command, _reserved) = self.into_process()?;
+                let spawned = command.spawn();
+                if spawned.is_err() {
+                    // the failed process took the terminal before trying to execute the command
+                    crate::jobs::reclaim_terminal();
+                }
+                match spawned {
+                    Ok(child) => Ok(ChildHandle::External(child)),
+                    Err(e) if e.kind() == ErrorKind::NotFound => {
+                        eprintln!("{name}: command not found");
+                        Ok(ChildHandle::Completed(exit_status_from_code(127)))
+                    }
+                    Err(e) => {
+                        log::error!("ERROR SPAWNING PROCESS: {e:?}");
+                        eprintln!("{name}: {}", error_description(&e));
+                        Ok(ChildHandle::Completed(exit_status_from_code(126)))
                     }
-                    Operator(Control(PipeAmp)) => todo!(),
-                    other_token => args.push(other_token),
                 }
             }
-
-            let simple_command = SimpleCommand {
-                location,
-                args: args.into_boxed_slice(),
-                stdin,
-                stdout,
-                stderr,
-            };
-            command_pipeline.push(simple_command);
-        }
-
-        if command_pipeline.is_empty() {
-            None
-        } else {
-            Some(Ok(Pipeline {
-                inner: command_pipeline,
-            }))
         }
     }
 }
 
-#[allow(unused)]
-pub fn collect_until_redir<I>(mut iter: I) -> (Box<[Token]>, Option<RedirectOperator>)
-where
-    I: Iterator<Item = Token>,
-{
-    let mut collector = Vec::new();
-    loop {
-        match iter.next() {
-            None => return (collector.into_boxed_slice(), None),
-            Some(Token::Operator(Operator::Redirect(redir_oper))) => {
-                return (collector.into_boxed_slice(), Some(redir_oper));
-            }
-            Some(other) => collector.push(other),
-        }
+impl SimpleCommand {
+    /// every descriptor above 2 the command refers to
+    fn raw_fds(&self) -> Vec<RawFd> {
+        let fds = self.fds.iter().filter_map(|(_, file)| file.as_ref());
+        [
+            self.stdin.raw_fd(),
+            self.stdout.raw_fd(),
+            self.stderr.raw_fd(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(fds.map(AsRawFd::as_raw_fd))
+        .collect()
     }
-}
 
-impl<'a, T: AsRef<str>> From<&'a T> for CommandStream<'a> {
-    fn from(value: &'a T) -> Self {
-        Self {
-            token_stream: TokenStream {
-                chars: value.as_ref().chars().peekable(),
-            }
-            .peekable(),
+    /// `exec name`: runs the external command in place of the shell, returning only if that fails
+    fn replace_shell(self) -> io::Result<ChildHandle> {
+        let CommandLocation::External(program) = &self.location else {
+            unreachable!("only external commands replace the shell")
+        };
+        let name = program.to_string_lossy().into_owned();
+        let found = if name.contains('/') {
+            program.exists()
+        } else {
+            name.first_executable_match_in_path().is_some()
+        };
+        if !found {
+            // checked first, as a failed exec leaves the shell with the command's redirections
+            eprintln!("exec: {name}: not found");
+            return Ok(ChildHandle::Completed(exit_status_from_code(127)));
         }
+
+        io::stdout().flush()?;
+        let (mut command, _reserved) = self.into_process()?;
+        let e = command.exec();
+        eprintln!("exec: {name}: {}", error_description(&e));
+        Ok(ChildHandle::Completed(exit_status_from_code(126)))
     }
-}
 
-impl Command for SimpleCommand {
-    fn spawn(self) -> io::Result<ChildHandle> {
+    /// The process running the external command, with the descriptors it inherits from the shell and its redirections.
+    ///
+    /// The descriptors returned keep free numbers the process gets a descriptor at from being taken while it starts:
+    /// they must stay open until then.
+    fn into_process(self) -> io::Result<(std::process::Command, Vec<OwnedFd>)> {
         let Self {
             location,
             args,
+            env,
             stdin,
             stdout,
             stderr,
+            fds,
+            process_group,
+            ..
         } = self;
-        match location {
-            CommandLocation::Builtin(bltn_command) => Ok(ChildHandle::Completed(
-                bltn_command.run_with(&args, stdout, stderr)?,
-            )),
-            CommandLocation::External(external) => {
-                let mut command = std::process::Command::new(&*external);
-                command.args(args);
-                command.stdin(stdin);
-                command.stdout(stdout);
-                command.stderr(stderr);
-                match command.spawn() {
-                    Ok(child) => Ok(ChildHandle::External(child)),
-                    Err(e) if e.kind() == ErrorKind::NotFound => {
-                        eprintln!("{}: command not found", external.to_string_lossy());
-                        Ok(ChildHandle::Completed(ExitStatus::from_raw(127)))
-                    }
-                    Err(e) => {
-                        log::error!("ERROR SPAWNING PROCESS: {e:?}");
-                        todo!("hanlde other error kind")
-                    }
+        let CommandLocation::External(external) = location else {
+            unreachable!("builtins run in the shell")
+        };
+
+        let mut descriptors = BTreeMap::new();
+        for (&fd, file) in SHELL_FDS.lock().unwrap().iter() {
+            descriptors.insert(fd, Some(file.try_clone()?));
+        }
+        descriptors.extend(fds);
+        for (fd, closed) in [
+            (STDIN_FILENO, stdin.is_closed()),
+            (STDOUT_FILENO, stdout.is_closed()),
+            (STDERR_FILENO, stderr.is_closed()),
+        ] {
+            if closed {
+                descriptors.insert(fd, None);
+            }
+        }
+        let reserved = reserve_fds(descriptors.keys().copied())?;
+
+        let mut command = std::process::Command::new(&*external);
+        command.args(args);
+        command.env_clear();
+        command.envs(VARIABLES.read().unwrap().exported());
+        command.envs(env);
+        command.stdin(stdin);
+        command.stdout(stdout);
+        command.stderr(stderr);
+        if let Some(group) = process_group {
+            command.process_group(group.id.map_or(0, Pid::as_raw));
+            let take_terminal = group.foreground;
+            // SAFETY: only async-signal-safe system calls are made in the forked child
+            unsafe {
+                command.pre_exec(move || crate::jobs::prepare_job_process(take_terminal));
+            }
+        }
+        if !descriptors.is_empty() {
+            // SAFETY: only async-signal-safe system calls are made in the forked child, and nothing is allocated
+            unsafe {
+                command.pre_exec(set_up_fds(descriptors));
+            }
+        }
+        Ok((command, reserved))
+    }
+}
+
+/// Opens `/dev/null` at each of `fds` above 2 that is not open yet,
+/// so that `std` does not pick one of them for a descriptor of its own while spawning.
+fn reserve_fds(fds: impl Iterator<Item = RawFd>) -> io::Result<Vec<OwnedFd>> {
+    let mut reserved = Vec::new();
+    for fd in fds.filter(|&fd| fd > STDERR_FILENO) {
+        if fcntl(fd, FcntlArg::F_GETFD) == Err(Errno::EBADF) {
+            let null = File::open("/dev/null")?;
+            dup3(null.as_raw_fd(), fd, OFlag::O_CLOEXEC)?;
+            // SAFETY: `fd` was just opened, and nothing else owns it
+            reserved.push(unsafe { OwnedFd::from_raw_fd(fd) });
+        }
+    }
+    Ok(reserved)
+}
+
+/// A `pre_exec` hook giving the child each of `descriptors` at the number it is keyed by, or closing it for `None`.
+fn set_up_fds(descriptors: BTreeMap<RawFd, Option<File>>) -> impl FnMut() -> io::Result<()> {
+    let above_targets = descriptors.keys().max().map_or(0, |fd| fd + 1);
+    let descriptors: Vec<(RawFd, Option<File>)> = descriptors.into_iter().collect();
+    // allocated before forking, the child must not allocate
+    let mut moved = vec![None; descriptors.len()];
+    move || {
+        // moved out of the way first, so that no source is overwritten by another before it is used
+        for ((_, file), moved) in zip(&descriptors, &mut moved) {
+            if let Some(file) = file {
+                *moved = Some(fcntl(
+                    file.as_raw_fd(),
+                    FcntlArg::F_DUPFD_CLOEXEC(above_targets),
+                )?);
+            }
+        }
+        for (&(fd, _), moved) in zip(&descriptors, &moved) {
+            match *moved {
+                Some(source) => {
+                    dup2(source, fd)?;
+                }
+                None => {
+                    let _ = close(fd);
                 }
             }
         }
+        Ok(())
     }
 }
 
+#[derive(Debug)]
 pub enum ChildHandle {
     Completed(ExitStatus),
     External(std::process::Child),
+    /// every command of a [`Pipeline`], in order. The last one determines the exit status
+    Pipeline(Vec<ChildHandle>),
+    /// a forked copy of the shell, running a builtin
+    Subshell(Pid),
+}
+
+/// what a [`ChildHandle`] was last seen doing
+#[derive(Debug, Clone, Copy)]
+pub enum ChildState {
+    Running,
+    Stopped,
+    Exited(ExitStatus),
 }
 
 impl ChildHandle {
-    fn wait(&mut self) -> io::Result<ExitStatus> {
+    pub fn wait(&mut self) -> io::Result<ExitStatus> {
+        loop {
+            if let ChildState::Exited(exit_status) = self.poll(WaitPidFlag::empty())? {
+                return Ok(exit_status);
+            }
+        }
+    }
+
+    /// blocks until the child either exits or gets stopped by a signal
+    pub fn wait_or_stop(&mut self) -> io::Result<ChildState> {
+        self.poll(WaitPidFlag::WUNTRACED)
+    }
+
+    /// Like [`wait_or_stop`][Self::wait_or_stop] but returns [`ChildState::Running`] instead of
+    /// blocking if nothing changed
+    pub fn try_wait(&mut self) -> io::Result<ChildState> {
+        self.poll(WaitPidFlag::WUNTRACED | WaitPidFlag::WNOHANG)
+    }
+
+    fn poll(&mut self, flags: WaitPidFlag) -> io::Result<ChildState> {
         match self {
-            Self::Completed(exit_status) => {
-                // todo this is the for temporary
-                Ok(*exit_status)
+            Self::Completed(exit_status) => Ok(ChildState::Exited(*exit_status)),
+            Self::External(external) => {
+                let pid = Pid::from_raw(external.id().cast_signed());
+                self.poll_process(pid, flags)
+            }
+            Self::Subshell(pid) => {
+                let pid = *pid;
+                self.poll_process(pid, flags)
+            }
+            Self::Pipeline(children) => {
+                let mut state = ChildState::Exited(ExitStatus::default());
+                for child in children {
+                    // poll every child so finished ones get reaped
+                    state = match (state, child.poll(flags)?) {
+                        (ChildState::Exited(_), child_state) => child_state,
+                        (ChildState::Stopped, _) | (_, ChildState::Stopped) => ChildState::Stopped,
+                        (ChildState::Running, _) => ChildState::Running,
+                    };
+                }
+                Ok(state)
             }
-            Self::External(external) => external.wait(),
+        }
+    }
+
+    /// the state of the process `pid` that `self` is for; once reaped, `self` becomes [`Self::Completed`]
+    fn poll_process(&mut self, pid: Pid, flags: WaitPidFlag) -> io::Result<ChildState> {
+        let exit_status = match waitpid(pid, Some(flags))? {
+            WaitStatus::Stopped(..) => return Ok(ChildState::Stopped),
+            WaitStatus::Exited(_, code) => exit_status_from_code(code),
+            WaitStatus::Signaled(_, signal, core_dumped) => {
+                ExitStatus::from_raw(signal as i32 | if core_dumped { 0x80 } else { 0 })
+            }
+            _still_alive_or_continued => return Ok(ChildState::Running),
+        };
+        // the process is reaped, so `std` must not wait on it again
+        *self = Self::Completed(exit_status);
+        Ok(ChildState::Exited(exit_status))
+    }
+
+    /// process id of the last process, if any process was spawned at all
+    pub fn pid(&self) -> Option<u32> {
+        match self {
+            Self::Completed(_) => None,
+            Self::External(external) => Some(external.id()),
+            Self::Subshell(pid) => Some(pid.as_raw().cast_unsigned()),
+            Self::Pipeline(children) => children.iter().rev().find_map(Self::pid),
+        }
+    }
+
+    /// id of the process group the processes were put in: that of the first process
+    pub fn process_group(&self) -> Option<Pid> {
+        match self {
+            Self::Completed(_) => None,
+            Self::External(external) => Some(Pid::from_raw(external.id().cast_signed())),
+            Self::Subshell(pid) => Some(*pid),
+            Self::Pipeline(children) => children.iter().find_map(Self::process_group),
         }
     }
 }
@@ -357,23 +550,12 @@ pub enum CommandLocation {
     External(Box<Path>),
 }
 
-impl TryFrom<&Token> for CommandLocation {
-    type Error = anyhow::Error;
-    fn try_from(token: &Token) -> Result<Self, Self::Error> {
-        if let Token::Word(Word::SimpleWord(string)) = &token {
-            return Ok(match string.parse::<BuiltinCommand>() {
-                Ok(builtin) => Self::Builtin(builtin),
-                Err(..) => Self::External(PathBuf::from(string).into_boxed_path()),
-            });
+impl From<&str> for CommandLocation {
+    fn from(name: &str) -> Self {
+        match name.parse::<BuiltinCommand>() {
+            Ok(builtin) => Self::Builtin(builtin),
+            Err(..) => Self::External(PathBuf::from(name).into_boxed_path()),
         }
-
-        Err(anyhow!("Token is not a simple word!"))
-    }
-}
-impl TryFrom<Token> for CommandLocation {
-    type Error = anyhow::Error;
-    fn try_from(token: Token) -> Result<Self, Self::Error> {
-        Self::try_from(&token)
     }
 }
 
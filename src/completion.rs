@@ -4,6 +4,7 @@ use rustyline::completion::Completer;
 use rustyline::{Helper, Highlighter, Hinter, Validator};
 use strum::IntoEnumIterator;
 
+use crate::variables::VARIABLES;
 use crate::BuiltinCommand;
 
 #[derive(Helper, Hinter, Highlighter, Validator)]
@@ -25,7 +26,13 @@ impl MyCompleter {
 }
 
 fn get_path_executables() -> Box<[String]> {
-    std::env::split_paths(&std::env::var("PATH").unwrap())
+    let path_variable = VARIABLES
+        .read()
+        .unwrap()
+        .get("PATH")
+        .unwrap_or_default()
+        .to_string();
+    std::env::split_paths(&path_variable)
         .filter_map(|path| {
             Some(
                 path.read_dir()
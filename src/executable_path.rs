@@ -1,3 +1,4 @@
+use crate::variables::VARIABLES;
 use std::ffi::OsStr;
 use std::os::unix::fs::MetadataExt;
 use std::path::Path;
@@ -20,7 +21,8 @@ where
     }
 
     fn first_executable_match_in_path(&self) -> Option<Box<Path>> {
-        for path_str in std::env::var("PATH").unwrap().split(':') {
+        let path_variable = VARIABLES.read().unwrap().get("PATH")?.to_string();
+        for path_str in path_variable.split(':') {
             let path_buf = Path::new(path_str).join(self.as_ref());
             let exe = path_buf.is_executable_file();
             if exe {
@@ -0,0 +1,706 @@
+//! Walks the [syntax tree][crate::ast], turning each node into runnable [`commands`][crate::commands].
+
+use crate::arithmetic;
+use crate::ast::{
+    self, AndOrList, ArithmeticForLoop, ForLoop, List, LogicalOperator, Redirect, WhileLoop,
+};
+use crate::builtin_commands::{BuiltinCommand, ExitRequest, LoopControl};
+use crate::commands::{
+    error_description, exit_status_from_code, spawn_subshell, ChildHandle, Command,
+    CommandLocation, Pipeline, PipelineCommand, ShellCommand, SimpleCommand,
+};
+use crate::expansion::{
+    self, expand_arithmetic_expression, expand_assignment_value, expand_here_document, expand_word,
+    expand_word_to_string, ExpansionError,
+};
+use crate::jobs::{self, JOBS};
+use crate::stream_target::{here_document, InStream, OutStream, SHELL_FDS};
+use crate::tokens::{RedirectOperator, Word};
+use crate::variables::VARIABLES;
+use itertools::Itertools;
+use nix::errno::Errno;
+use nix::libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::sys::signal::Signal;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, stderr, stdout, Stderr, Stdout, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::ExitStatus;
+
+thread_local! {
+    /// number of loops being executed on this thread, which `break` and `continue` can leave
+    static LOOP_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// how many loops enclose the command being executed
+pub fn loop_depth() -> usize {
+    LOOP_DEPTH.get()
+}
+
+/// The streams commands use unless they redirect them.
+#[derive(Debug)]
+pub struct Io {
+    pub stdin: InStream,
+    pub stdout: OutStream<Stdout>,
+    pub stderr: OutStream<Stderr>,
+    /// descriptors above 2 redirected for a whole compound command, `None` for those it closed
+    pub others: BTreeMap<RawFd, Option<File>>,
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self {
+            stdin: InStream::Std,
+            stdout: OutStream::Std(stdout()),
+            stderr: OutStream::Std(stderr()),
+            others: BTreeMap::new(),
+        }
+    }
+}
+
+impl Io {
+    /// every descriptor above 2 the streams refer to
+    pub fn raw_fds(&self) -> Vec<RawFd> {
+        let others = self.others.values().flatten().map(AsRawFd::as_raw_fd);
+        [
+            self.stdin.raw_fd(),
+            self.stdout.raw_fd(),
+            self.stderr.raw_fd(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(others)
+        .collect()
+    }
+
+    fn try_clone_others(&self) -> io::Result<BTreeMap<RawFd, Option<File>>> {
+        self.others
+            .iter()
+            .map(|(&fd, file)| Ok((fd, file.as_ref().map(File::try_clone).transpose()?)))
+            .collect()
+    }
+}
+
+impl List {
+    pub fn execute(&self) -> io::Result<ExitStatus> {
+        self.execute_with(&Io::default())
+    }
+
+    pub fn execute_with(&self, io: &Io) -> io::Result<ExitStatus> {
+        let mut exit_status = ExitStatus::default();
+        for item in &self.items {
+            if item.asynchronous {
+                let handle = item.and_or.spawn(io)?;
+                let pid = handle.pid();
+                let id = JOBS.lock().unwrap().add(item.and_or.to_string(), handle).id;
+                if VARIABLES.read().unwrap().interactive {
+                    match pid {
+                        Some(pid) => eprintln!("[{id}] {pid}"),
+                        None => eprintln!("[{id}]"),
+                    }
+                }
+                exit_status = ExitStatus::default();
+                set_last_exit_status(exit_status);
+            } else {
+                exit_status = item.and_or.execute(io)?;
+                if interrupted(exit_status) {
+                    // Ctrl-C or Ctrl-Z ends the rest of the list as well
+                    break;
+                }
+            }
+        }
+        Ok(exit_status)
+    }
+}
+
+impl AndOrList {
+    fn execute(&self, io: &Io) -> io::Result<ExitStatus> {
+        let mut exit_status = self.head.execute(io)?;
+        for (operator, pipeline) in &self.tail {
+            let should_run = match operator {
+                LogicalOperator::And => exit_status.success(),
+                LogicalOperator::Or => !exit_status.success(),
+            };
+            if should_run {
+                exit_status = pipeline.execute(io)?;
+            }
+        }
+        Ok(exit_status)
+    }
+
+    /// starts the list in a subshell of its own, without waiting for it to finish
+    fn spawn(&self, io: &Io) -> io::Result<ChildHandle> {
+        spawn_subshell(jobs::new_process_group(false), io.raw_fds(), || {
+            self.execute(io)
+        })
+    }
+}
+
+impl ast::Pipeline {
+    fn execute(&self, io: &Io) -> io::Result<ExitStatus> {
+        let mut exit_status = jobs::wait_for_foreground(self.spawn(io)?, self)?;
+
+        if self.negated {
+            exit_status = exit_status_from_code(exit_status.success().into());
+        }
+        set_last_exit_status(exit_status);
+        Ok(exit_status)
+    }
+
+    fn spawn(&self, io: &Io) -> io::Result<ChildHandle> {
+        if let [command @ (ast::Command::Arithmetic(_) | ast::Command::Compound(..))] =
+            self.commands.as_slice()
+        {
+            // the shell evaluates it right away, waiting for the commands inside as it goes
+            return Ok(ChildHandle::Completed(command.execute(io)?));
+        }
+
+        expansion::take_substitution_status();
+        match self.prepare(io) {
+            // a command made only of assignments has the status of its last command substitution
+            Ok(pipeline) if pipeline.inner.is_empty() => Ok(ChildHandle::Completed(
+                expansion::take_substitution_status().unwrap_or_default(),
+            )),
+            Ok(pipeline) => pipeline.spawn(),
+            Err(e) => {
+                // the commands of any process substitution run on without being waited for
+                expansion::take_process_substitutions();
+                Ok(ChildHandle::Completed(report_preparation_error(&e)?))
+            }
+        }
+    }
+
+    /// connects the commands with pipes and applies their redirections
+    fn prepare(&self, io: &Io) -> anyhow::Result<Pipeline> {
+        // a lone command can change the shell itself, as `exec` does
+        let in_shell_environment = self.commands.len() == 1;
+        let mut inner = Vec::with_capacity(self.commands.len());
+        let mut following_reader = None;
+
+        for (index, command) in self.commands.iter().enumerate() {
+            let stdin = match following_reader.take() {
+                Some(reader) => reader,
+                None => io.stdin.try_clone()?,
+            };
+            let stdout = if index + 1 < self.commands.len() {
+                let (reader, writer) = crate::stream_target::pipe();
+                following_reader = Some(InStream::PipeReader(reader));
+                OutStream::PipeWriter(writer)
+            } else {
+                io.stdout.try_clone()?
+            };
+
+            let stderr = io.stderr.try_clone()?;
+            // `|&` is short for `2>&1 |`, applied after the command's own redirections
+            let pipes_stderr = self.pipes_stderr[index];
+            match command {
+                ast::Command::Simple(simple_command) => {
+                    let streams = Streams::new(stdin, stdout, stderr, io.try_clone_others()?);
+                    let prepared = simple_command.prepare(streams, in_shell_environment)?;
+                    if let Some(mut prepared) = prepared {
+                        if pipes_stderr {
+                            prepared.stderr = prepared.stdout.try_clone_as()?;
+                        }
+                        inner.push(PipelineCommand::Simple(prepared));
+                    }
+                }
+                compound => {
+                    let stderr = if pipes_stderr {
+                        stdout.try_clone_as()?
+                    } else {
+                        stderr
+                    };
+                    inner.push(PipelineCommand::Shell(ShellCommand {
+                        command: compound.clone(),
+                        io: Io {
+                            stdin,
+                            stdout,
+                            stderr,
+                            others: io.try_clone_others()?,
+                        },
+                    }));
+                }
+            }
+        }
+
+        Ok(Pipeline {
+            inner,
+            process_group: jobs::new_process_group(true),
+        })
+    }
+}
+
+impl ast::Command {
+    /// evaluates the command in the shell itself, waiting for any processes it starts
+    pub fn execute(&self, io: &Io) -> io::Result<ExitStatus> {
+        match self {
+            Self::Simple(_) => ast::Pipeline {
+                negated: false,
+                commands: vec![self.clone()],
+                pipes_stderr: vec![false],
+            }
+            .execute(io),
+            Self::Arithmetic(expression) => {
+                let value = expand_arithmetic_expression(expression)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|expression| Ok(arithmetic::evaluate(&expression)?));
+                Ok(match value {
+                    Ok(value) => exit_status_from_code((value == 0).into()),
+                    Err(e) => report_preparation_error(&e)?,
+                })
+            }
+            Self::Compound(compound_command, redirects) if redirects.is_empty() => {
+                compound_command.execute(io)
+            }
+            Self::Compound(compound_command, redirects) => {
+                let mut streams = Streams::new(
+                    io.stdin.try_clone()?,
+                    io.stdout.try_clone()?,
+                    io.stderr.try_clone()?,
+                    io.try_clone_others()?,
+                );
+                let applied = streams.apply(redirects);
+                // the commands of any process substitution run on without being waited for
+                expansion::take_process_substitutions();
+                if let Err(e) = applied {
+                    return report_preparation_error(&e);
+                }
+                compound_command.execute(&Io {
+                    stdin: streams.stdin,
+                    stdout: streams.stdout,
+                    stderr: streams.stderr,
+                    others: streams.others,
+                })
+            }
+        }
+    }
+}
+
+impl ast::CompoundCommand {
+    fn execute(&self, io: &Io) -> io::Result<ExitStatus> {
+        match self {
+            Self::If(if_clause) => {
+                for (condition, body) in &if_clause.branches {
+                    if condition.execute_with(io)?.success() {
+                        return body.execute_with(io);
+                    }
+                }
+                match &if_clause.else_branch {
+                    Some(else_branch) => else_branch.execute_with(io),
+                    // no branch ran
+                    None => Ok(ExitStatus::default()),
+                }
+            }
+            Self::While(WhileLoop {
+                until,
+                condition,
+                body,
+            }) => run_loop(|| {
+                let tested = condition.execute_with(io)?;
+                if interrupted(tested) {
+                    return Ok(Some(tested));
+                }
+                if tested.success() == *until {
+                    return Ok(None);
+                }
+                body.execute_with(io).map(Some)
+            }),
+            Self::For(ForLoop { name, words, body }) => {
+                let values = match words {
+                    Some(words) => {
+                        let mut fields = Vec::new();
+                        for word in words {
+                            match expand_word(word) {
+                                Ok(expanded) => fields.extend(expanded),
+                                Err(e) => return report_preparation_error(&e.into()),
+                            }
+                        }
+                        fields
+                    }
+                    None => VARIABLES.read().unwrap().positional_parameters.clone(),
+                };
+
+                let mut values = values.into_iter();
+                let mut failure = None;
+                let exit_status = run_loop(|| {
+                    let Some(value) = values.next() else {
+                        return Ok(None);
+                    };
+                    if let Err(e) = VARIABLES.write().unwrap().set(name, value) {
+                        // such as a readonly variable, which ends the loop
+                        failure = Some(report_preparation_error(&e.into())?);
+                        return Ok(None);
+                    }
+                    body.execute_with(io).map(Some)
+                })?;
+                Ok(failure.unwrap_or(exit_status))
+            }
+            Self::ArithmeticFor(ArithmeticForLoop {
+                init,
+                condition,
+                update,
+                body,
+            }) => {
+                let evaluate = |expression: &Word| -> anyhow::Result<i64> {
+                    let expression = expand_arithmetic_expression(expression)?;
+                    // "If any expression is omitted, it behaves as if it evaluates to 1."
+                    if expression.trim().is_empty() {
+                        return Ok(1);
+                    }
+                    Ok(arithmetic::evaluate(&expression)?)
+                };
+                if let Err(e) = evaluate(init) {
+                    return report_preparation_error(&e);
+                }
+
+                let mut first_iteration = true;
+                let mut failure = None;
+                let exit_status = run_loop(|| {
+                    // `continue` goes on to the update as well
+                    let updated = match std::mem::replace(&mut first_iteration, false) {
+                        true => Ok(0),
+                        false => evaluate(update),
+                    };
+                    match updated.and_then(|_| evaluate(condition)) {
+                        Ok(0) => Ok(None),
+                        Ok(_) => body.execute_with(io).map(Some),
+                        Err(e) => {
+                            failure = Some(report_preparation_error(&e)?);
+                            Ok(None)
+                        }
+                    }
+                })?;
+                Ok(failure.unwrap_or(exit_status))
+            }
+        }
+    }
+}
+
+/// Runs the iterations of a loop until `iteration` returns `None`, applying the `break` and `continue` in them.
+///
+/// The status of the loop is that of the last iteration, 0 if there was none.
+fn run_loop(
+    mut iteration: impl FnMut() -> io::Result<Option<ExitStatus>>,
+) -> io::Result<ExitStatus> {
+    LOOP_DEPTH.set(LOOP_DEPTH.get() + 1);
+    let mut exit_status = ExitStatus::default();
+    let result = loop {
+        match iteration() {
+            Ok(Some(status)) if interrupted(status) => break Ok(status),
+            Ok(Some(status)) => exit_status = status,
+            Ok(None) => break Ok(exit_status),
+            Err(mut e) => match e.get_mut().and_then(|e| e.downcast_mut::<LoopControl>()) {
+                // leaves this loop on its way to an outer one
+                Some(control) if control.loops > 1 => {
+                    control.loops -= 1;
+                    break Err(e);
+                }
+                Some(control) if control.continues => exit_status = control.exit_status,
+                Some(control) => break Ok(control.exit_status),
+                None => break Err(e),
+            },
+        }
+    };
+    LOOP_DEPTH.set(LOOP_DEPTH.get() - 1);
+    result
+}
+
+/// true if the command was killed by the SIGINT a Ctrl-C sends, or stopped by the SIGTSTP of a
+/// Ctrl-Z, which the shell stops at as well
+fn interrupted(exit_status: ExitStatus) -> bool {
+    exit_status.signal() == Some(Signal::SIGINT as i32) || exit_status.stopped_signal().is_some()
+}
+
+/// updates `$?`
+fn set_last_exit_status(exit_status: ExitStatus) {
+    VARIABLES.write().unwrap().last_exit_status = exit_status;
+}
+
+/// Reports `e`, giving the status of the command that failed.
+///
+/// "If parameter is null or unset, [...] a non-interactive shell exits":
+/// for `${NAME:?message}` in a script, an [`ExitRequest`] unwinds up to what it ends.
+fn report_preparation_error(e: &anyhow::Error) -> io::Result<ExitStatus> {
+    log::warn!("failed to prepare pipeline: {e:?}");
+    eprintln!("{e}");
+    let null_or_unset = matches!(
+        e.downcast_ref::<ExpansionError>(),
+        Some(ExpansionError::NullOrUnset(..))
+    );
+    if null_or_unset && !VARIABLES.read().unwrap().interactive {
+        return Err(io::Error::other(ExitRequest(1)));
+    }
+    Ok(exit_status_from_code(1))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedirectError {
+    #[error("{0}: {reason}", reason = error_description(.1))]
+    Open(String, io::Error),
+    #[error("{0}: Bad file descriptor")]
+    BadFileDescriptor(String),
+    #[error("{0}: ambiguous redirect")]
+    Ambiguous(String),
+    #[error("{0}: cannot overwrite existing file")]
+    Clobber(String),
+}
+
+fn open_redirect(target: &str, options: &OpenOptions) -> Result<File, RedirectError> {
+    options
+        .open(Path::new(target))
+        .map_err(|e| RedirectError::Open(target.to_string(), e))
+}
+
+/// Opens `target` for a redirection that writes to it: `>>` appends to the file, the others replace its contents.
+///
+/// With `noclobber` set, only `>|` replaces the contents of an existing regular file.
+fn open_for_writing(target: &str, operator: RedirectOperator) -> Result<File, RedirectError> {
+    let mut options = OpenOptions::new();
+    options.create(true).read(true).write(true);
+    if operator.appends() {
+        options.append(true);
+    } else if matches!(operator, RedirectOperator::Clobber) || !VARIABLES.read().unwrap().noclobber
+    {
+        options.truncate(true);
+    } else {
+        match std::fs::metadata(target) {
+            Ok(metadata) if metadata.is_file() => {
+                return Err(RedirectError::Clobber(target.to_string()))
+            }
+            // such as `/dev/null`
+            Ok(_) => {}
+            // fails if the file gets created in the meantime
+            Err(_) => {
+                options.create_new(true);
+            }
+        }
+    }
+    open_redirect(target, &options)
+}
+
+/// The descriptors of a command while its redirections are applied.
+#[derive(Debug)]
+struct Streams {
+    stdin: InStream,
+    stdout: OutStream<Stdout>,
+    stderr: OutStream<Stderr>,
+    /// descriptors above 2, `None` once closed. Any other one is the shell's, see [`SHELL_FDS`]
+    others: BTreeMap<RawFd, Option<File>>,
+    /// which of 0, 1 and 2 were redirected
+    redirected: Vec<RawFd>,
+}
+
+impl Streams {
+    const fn new(
+        stdin: InStream,
+        stdout: OutStream<Stdout>,
+        stderr: OutStream<Stderr>,
+        others: BTreeMap<RawFd, Option<File>>,
+    ) -> Self {
+        Self {
+            stdin,
+            stdout,
+            stderr,
+            others,
+            redirected: Vec::new(),
+        }
+    }
+
+    /// makes `fd` refer to `file`, or closes it for `None`
+    fn set(&mut self, fd: RawFd, file: Option<File>) {
+        match (fd, file) {
+            (STDIN_FILENO, Some(file)) => self.stdin = InStream::File(file),
+            (STDIN_FILENO, None) => self.stdin = InStream::Closed,
+            (STDOUT_FILENO, Some(file)) => self.stdout = OutStream::File(file),
+            (STDOUT_FILENO, None) => self.stdout = OutStream::Closed,
+            (STDERR_FILENO, Some(file)) => self.stderr = OutStream::File(file),
+            (STDERR_FILENO, None) => self.stderr = OutStream::Closed,
+            (fd, file) => {
+                self.others.insert(fd, file);
+                return;
+            }
+        }
+        self.redirected.push(fd);
+    }
+
+    /// a new handle to what `fd` refers to, for `n>&fd` or `n<&fd`
+    fn duplicate(&self, fd: RawFd) -> io::Result<File> {
+        let duplicated = match fd {
+            STDIN_FILENO => self.stdin.try_clone_fd(),
+            STDOUT_FILENO => self.stdout.try_clone_fd(),
+            STDERR_FILENO => self.stderr.try_clone_fd(),
+            _ => match self.others.get(&fd) {
+                Some(Some(file)) => file.try_clone().map(OwnedFd::from),
+                Some(None) => Err(Errno::EBADF.into()),
+                None => match SHELL_FDS.lock().unwrap().get(&fd) {
+                    Some(file) => file.try_clone().map(OwnedFd::from),
+                    None => Err(Errno::EBADF.into()),
+                },
+            },
+        };
+        duplicated.map(File::from)
+    }
+
+    /// applies `redirects` left to right, so `>out 2>&1` differs from `2>&1 >out`
+    fn apply(&mut self, redirects: &[Redirect]) -> anyhow::Result<()> {
+        for redirect in redirects {
+            use RedirectOperator as R;
+
+            let fd = redirect.fd();
+            let target = match redirect.here_document {
+                // the delimiter is not expanded
+                Some(_) => String::new(),
+                None => expand_word_to_string(&redirect.target)?,
+            };
+            match redirect.operator {
+                R::Input => {
+                    self.set(
+                        fd,
+                        Some(open_redirect(&target, OpenOptions::new().read(true))?),
+                    );
+                }
+                R::ReadWrite => {
+                    let file = open_redirect(
+                        &target,
+                        OpenOptions::new().read(true).write(true).create(true),
+                    )?;
+                    self.set(fd, Some(file));
+                }
+                R::Output | R::Append | R::Clobber => {
+                    let file = open_for_writing(&target, redirect.operator)?;
+                    self.set(fd, Some(file));
+                }
+                // "`>&word` [...] is semantically equivalent to `>word 2>&1`" when word is not a number
+                R::DuplicateOutput
+                    if fd == STDOUT_FILENO && target != "-" && target.parse::<RawFd>().is_err() =>
+                {
+                    let file = open_for_writing(&target, RedirectOperator::Output)?;
+                    self.set(STDERR_FILENO, Some(file.try_clone()?));
+                    self.set(STDOUT_FILENO, Some(file));
+                }
+                R::OutputAndError | R::AppendOutputAndError => {
+                    let file = open_for_writing(&target, redirect.operator)?;
+                    self.set(STDERR_FILENO, Some(file.try_clone()?));
+                    self.set(STDOUT_FILENO, Some(file));
+                }
+                R::HereDocument | R::HereDocumentStripTabs => {
+                    let body = redirect
+                        .here_document
+                        .as_ref()
+                        .expect("the parser reads the lines of every here-document");
+                    let content = expand_here_document(body)?;
+                    self.set(fd, Some(here_document(content)?));
+                }
+                R::HereString => self.set(fd, Some(here_document(target + "\n")?)),
+                R::DuplicateInput | R::DuplicateOutput => {
+                    let file = if target == "-" {
+                        None
+                    } else {
+                        let source = target
+                            .parse::<RawFd>()
+                            .map_err(|_| RedirectError::Ambiguous(target.clone()))?;
+                        let file = self
+                            .duplicate(source)
+                            .map_err(|_| RedirectError::BadFileDescriptor(target.clone()))?;
+                        Some(file)
+                    };
+                    self.set(fd, file);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `exec` without a command: the redirections stay in effect for the rest of the shell's life
+    fn make_permanent(self) -> io::Result<()> {
+        stdout().flush()?;
+        stderr().flush()?;
+        for fd in self.redirected.into_iter().unique() {
+            let (duplicated, closed) = match fd {
+                STDIN_FILENO => (self.stdin.try_clone_fd(), self.stdin.is_closed()),
+                STDOUT_FILENO => (self.stdout.try_clone_fd(), self.stdout.is_closed()),
+                _ => (self.stderr.try_clone_fd(), self.stderr.is_closed()),
+            };
+            if closed {
+                let _ = nix::unistd::close(fd);
+            } else {
+                nix::unistd::dup2(duplicated?.as_raw_fd(), fd)?;
+            }
+        }
+        let mut shell_fds = SHELL_FDS.lock().unwrap();
+        for (fd, file) in self.others {
+            match file {
+                Some(file) => shell_fds.insert(fd, file),
+                None => shell_fds.remove(&fd),
+            };
+        }
+        Ok(())
+    }
+}
+
+impl ast::SimpleCommand {
+    /// `None` if there is no command name: the assignments were made to the shell's variables
+    fn prepare(
+        &self,
+        mut streams: Streams,
+        in_shell_environment: bool,
+    ) -> anyhow::Result<Option<SimpleCommand>> {
+        let mut fields = Vec::new();
+        for word in &self.words {
+            fields.extend(expand_word(word)?);
+        }
+        streams.apply(&self.redirects)?;
+
+        let exec_without_command = matches!(
+            fields.as_slice(),
+            [name] if matches!(name.parse(), Ok(BuiltinCommand::Exec))
+        );
+        if exec_without_command && in_shell_environment {
+            streams.make_permanent()?;
+            let Io {
+                stdin,
+                stdout,
+                stderr,
+                others,
+            } = Io::default();
+            streams = Streams::new(stdin, stdout, stderr, others);
+        }
+
+        let mut assigned = Vec::with_capacity(self.assignments.len());
+        for assignment in &self.assignments {
+            let value = expand_assignment_value(&assignment.value)?;
+            if fields.is_empty() {
+                // assignments are expanded in order, so later ones see the earlier values
+                VARIABLES.write().unwrap().set(&assignment.name, value)?;
+            } else {
+                assigned.push((assignment.name.clone(), value));
+            }
+        }
+
+        for file in expansion::take_process_substitutions() {
+            // available at the number in its path, unless a redirection took it over
+            streams.others.entry(file.as_raw_fd()).or_insert(Some(file));
+        }
+
+        let Some((command_field, arg_fields)) = fields.split_first() else {
+            return Ok(None);
+        };
+        let location = CommandLocation::from(command_field.as_str());
+        let args = arg_fields.into();
+
+        Ok(Some(SimpleCommand {
+            location,
+            args,
+            env: assigned,
+            stdin: streams.stdin,
+            stdout: streams.stdout,
+            stderr: streams.stderr,
+            fds: streams.others.into_iter().collect(),
+            in_shell_environment,
+            process_group: None,
+        }))
+    }
+}
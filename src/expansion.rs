@@ -0,0 +1,781 @@
+//! "Expansion is performed on the command line after it has been split into tokens."
+//!
+//! Words reach this module split into the quoted and unquoted parts the lexer found.
+//! Expanding them expands braces, substitutes parameters, splits the results into fields and removes the quotes.
+//!
+//! -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Shell-Expansions)
+
+use crate::arithmetic::{self, ArithmeticError};
+use crate::ast::List;
+use crate::brace;
+use crate::commands::{exit_code, spawn_subshell};
+use crate::executor::Io;
+use crate::glob;
+use crate::jobs::{self, JOBS};
+use crate::parser::{ParseError, Parser};
+use crate::pattern::{self, Pattern};
+use crate::stream_target::{pipe, InStream, OutStream};
+use crate::tokens::{
+    ConditionalOperator, ParameterExpansion, ParameterOperation, ProcessDirection, ReplaceMode,
+    Word, WordPart, WordPartKind,
+};
+use crate::variables::{is_valid_name, VariableError, VariableTable, VARIABLES};
+use nix::unistd::{Uid, User};
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::process::ExitStatus;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ExpansionError {
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    /// `${NAME:?message}` with `NAME` unset or null
+    #[error("{0}: {1}")]
+    NullOrUnset(String, String),
+    #[error("{0}: substring expression < 0")]
+    NegativeSubstring(String),
+    #[error(transparent)]
+    Variable(#[from] VariableError),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Arithmetic(#[from] ArithmeticError),
+}
+
+thread_local! {
+    /// the status of the most recent command substitution, see [`take_substitution_status`]
+    static SUBSTITUTION_STATUS: Cell<Option<ExitStatus>> = const { Cell::new(None) };
+    /// the shell's end of the pipes of the process substitutions started since the last
+    /// [`take_process_substitutions`]
+    static PROCESS_SUBSTITUTIONS: RefCell<Vec<File>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The exit status of the last command substitution since the previous call, if there was one.
+///
+/// A command without a command name has this status instead of `0`.
+pub fn take_substitution_status() -> Option<ExitStatus> {
+    SUBSTITUTION_STATUS.take()
+}
+
+/// The shell's end of the pipe of each process substitution started while expanding words since the previous call.
+///
+/// The command the words belong to needs the files at the descriptor numbers in their paths.
+/// It is not waited for together with their commands, which run on by themselves.
+pub fn take_process_substitutions() -> Vec<File> {
+    PROCESS_SUBSTITUTIONS.take()
+}
+
+/// A piece of an expanded word.
+#[derive(Debug, Clone)]
+struct Piece {
+    text: String,
+    /// quoted text is neither split into fields nor treated as a pattern
+    quoted: bool,
+    /// the result of an expansion, rather than text typed as part of the word
+    expanded: bool,
+    /// ends the current field, like between the positional parameters of `"$@"`
+    field_break: bool,
+}
+
+impl Piece {
+    fn literal(text: impl Into<String>, quoted: bool) -> Self {
+        Self {
+            text: text.into(),
+            quoted,
+            expanded: false,
+            field_break: false,
+        }
+    }
+
+    fn expanded(text: impl Into<String>, quoted: bool) -> Self {
+        Self {
+            expanded: true,
+            ..Self::literal(text, quoted)
+        }
+    }
+
+    fn field_break() -> Self {
+        Self {
+            field_break: true,
+            ..Self::expanded("", false)
+        }
+    }
+}
+
+/// Expands `word` into the fields it becomes part of the command, with quotes removed.
+///
+/// Braces are expanded first, each of the words they produce is expanded on its own.
+/// Unquoted results of expansions are split on the characters of `IFS`, so a word may produce no fields at all.
+/// Fields with unquoted pattern characters are replaced by the pathnames they match.
+pub fn expand_word(word: &Word) -> Result<Vec<String>, ExpansionError> {
+    let mut fields = Vec::new();
+    for parts in brace::expand(&word.parts) {
+        let pieces = expand_parts(&parts, false, TildePrefixes::AtStart)?;
+        fields.extend(
+            split_fields(&pieces)
+                .into_iter()
+                .flat_map(Field::expand_pathnames),
+        );
+    }
+    Ok(fields)
+}
+
+/// Expands `word` into a single string, without splitting it into fields.
+///
+/// Used where only one word is allowed, such as the target of a redirection.
+pub fn expand_word_to_string(word: &Word) -> Result<String, ExpansionError> {
+    expand_to_string(word, false, TildePrefixes::AtStart)
+}
+
+/// Expands the body of a here-document, which behaves as if it were within double quotes.
+pub fn expand_here_document(body: &Word) -> Result<String, ExpansionError> {
+    expand_to_string(body, true, TildePrefixes::Nowhere)
+}
+
+/// Expands the value of an assignment, in which a tilde prefix may also follow any unquoted `:`.
+pub fn expand_assignment_value(word: &Word) -> Result<String, ExpansionError> {
+    expand_to_string(word, false, TildePrefixes::InAssignment)
+}
+
+/// Expands an arithmetic expression, in which `~` is an operator rather than a tilde prefix.
+pub fn expand_arithmetic_expression(word: &Word) -> Result<String, ExpansionError> {
+    expand_to_string(word, false, TildePrefixes::Nowhere)
+}
+
+fn expand_to_string(
+    word: &Word,
+    in_double_quotes: bool,
+    tildes: TildePrefixes,
+) -> Result<String, ExpansionError> {
+    Ok(expand_parts(&word.parts, in_double_quotes, tildes)?
+        .into_iter()
+        .map(|piece| {
+            if piece.field_break {
+                " ".to_string()
+            } else {
+                piece.text
+            }
+        })
+        .collect())
+}
+
+/// expands `word` into a pattern in which only unquoted characters keep their special meaning
+fn expand_to_pattern(word: &Word) -> Result<Pattern, ExpansionError> {
+    let pattern: String = expand_parts(&word.parts, false, TildePrefixes::AtStart)?
+        .into_iter()
+        .map(|piece| {
+            if piece.quoted {
+                pattern::escape(&piece.text)
+            } else {
+                piece.text
+            }
+        })
+        .collect();
+    Ok(Pattern::parse(&pattern))
+}
+
+fn expand_parts(
+    parts: &[WordPart],
+    in_double_quotes: bool,
+    tildes: TildePrefixes,
+) -> Result<Vec<Piece>, ExpansionError> {
+    let mut pieces = Vec::new();
+    for (index, part) in parts.iter().enumerate() {
+        match &part.kind {
+            WordPartKind::Literal(text) if in_double_quotes || tildes == TildePrefixes::Nowhere => {
+                pieces.push(Piece::literal(text, in_double_quotes));
+            }
+            WordPartKind::Literal(text) => pieces.extend(expand_tilde_prefixes(
+                text,
+                tildes == TildePrefixes::InAssignment,
+                index == 0,
+                index + 1 == parts.len(),
+            )),
+            WordPartKind::Escaped(c) => pieces.push(Piece::literal(*c, true)),
+            WordPartKind::SingleQuoted(text) => pieces.push(Piece::literal(text, true)),
+            WordPartKind::DoubleQuoted(inner) => {
+                let inner = expand_parts(inner, true, TildePrefixes::Nowhere)?;
+                // an empty quoted piece keeps `""` as an (empty) field.
+                // Only `"$@"` without positional parameters leaves an unquoted piece,
+                // and it must not produce a field
+                if inner.iter().all(|piece| piece.quoted) {
+                    pieces.push(Piece::literal("", true));
+                }
+                pieces.extend(inner);
+            }
+            WordPartKind::Parameter(parameter) => {
+                pieces.extend(expand_parameter(parameter, in_double_quotes)?);
+            }
+            WordPartKind::CommandSubstitution(source) => pieces.push(Piece::expanded(
+                substitute_command(source)?,
+                in_double_quotes,
+            )),
+            WordPartKind::ProcessSubstitution(direction, source) => {
+                pieces.push(Piece::literal(
+                    substitute_process(*direction, source)?,
+                    true,
+                ));
+            }
+            WordPartKind::Arithmetic(expression) => {
+                let value = arithmetic::evaluate(&expand_arithmetic_expression(expression)?)?;
+                pieces.push(Piece::expanded(value.to_string(), in_double_quotes));
+            }
+            WordPartKind::BadSubstitution(text) => {
+                return Err(ExpansionError::BadSubstitution(text.clone()))
+            }
+        }
+    }
+    Ok(pieces)
+}
+
+/// where a word may start a tilde prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TildePrefixes {
+    Nowhere,
+    /// only as the first character of the word
+    AtStart,
+    /// also after every unquoted `:`, as in `PATH=~/bin:~/.cargo/bin`
+    InAssignment,
+}
+
+/// "If a word begins with an unquoted tilde character (‘~’), all of the characters up to the first unquoted slash
+/// (or all characters, if there is no unquoted slash) are considered a tilde-prefix."
+///
+/// `text` is an unquoted literal part of a word, at its start if `at_word_start` and at its end if `at_word_end`.
+/// The expanded prefixes are quoted, so they are neither split into fields nor treated as patterns.
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Tilde-Expansion)
+fn expand_tilde_prefixes(
+    text: &str,
+    in_assignment: bool,
+    at_word_start: bool,
+    at_word_end: bool,
+) -> Vec<Piece> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    let mut may_start_prefix = at_word_start;
+    loop {
+        if may_start_prefix && rest.starts_with('~') {
+            let prefix_length = match rest.find(|c| c == '/' || (in_assignment && c == ':')) {
+                Some(length) => Some(length),
+                // the prefix would take in a quoted or expanded part of the word otherwise
+                None => at_word_end.then_some(rest.len()),
+            };
+            if let Some(length) = prefix_length {
+                if let Some(expanded) = tilde_expansion(&rest[1..length]) {
+                    pieces.push(Piece::expanded(expanded, true));
+                    rest = &rest[length..];
+                }
+            }
+        }
+        match rest.find(':').filter(|_| in_assignment) {
+            Some(colon) => {
+                pieces.push(Piece::literal(&rest[..=colon], false));
+                rest = &rest[colon + 1..];
+                may_start_prefix = true;
+            }
+            None => {
+                pieces.push(Piece::literal(rest, false));
+                return pieces;
+            }
+        }
+    }
+}
+
+/// the directory a tilde prefix stands for, with `login` the characters following the `~`
+fn tilde_expansion(login: &str) -> Option<String> {
+    match login {
+        "" => get_variable("HOME").or_else(|| {
+            let user = User::from_uid(Uid::current()).ok()??;
+            Some(user.dir.to_string_lossy().into_owned())
+        }),
+        "+" => get_variable("PWD"),
+        "-" => get_variable("OLDPWD"),
+        name => {
+            let user = User::from_name(name).ok()??;
+            Some(user.dir.to_string_lossy().into_owned())
+        }
+    }
+}
+
+/// "Bash performs the expansion by executing command in a subshell environment
+/// and replacing the command substitution with the standard output of the command,
+/// with any trailing newlines deleted."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Command-Substitution)
+fn substitute_command(source: &str) -> Result<String, ExpansionError> {
+    // parsed before forking, so that a syntax error fails the expansion
+    let lists = Parser::from(&source).collect::<Result<Vec<_>, _>>()?;
+    let (mut reader, writer) = pipe();
+    let io = Io {
+        stdout: OutStream::PipeWriter(writer),
+        ..Io::default()
+    };
+    let mut child = spawn_subshell(None, io.raw_fds(), || {
+        // those of the word being expanded belong to its command, not to the commands substituted
+        PROCESS_SUBSTITUTIONS.take();
+        execute_lists(&lists, &io)
+    })?;
+    // the reader only reaches the end once every handle to the writer is closed
+    drop(io);
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output)?;
+    let exit_status = child.wait()?;
+    SUBSTITUTION_STATUS.set(Some(exit_status));
+    VARIABLES.write().unwrap().last_exit_status = exit_status;
+
+    let mut output = String::from_utf8_lossy(&output).into_owned();
+    output.truncate(output.trim_end_matches('\n').len());
+    Ok(output)
+}
+
+/// "The process list is run asynchronously, and its input or output appears as a filename."
+///
+/// The filename is `/dev/fd/N`, with `N` the descriptor of the shell's end of a pipe connected to the commands.
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Process-Substitution)
+fn substitute_process(direction: ProcessDirection, source: &str) -> Result<String, ExpansionError> {
+    let lists = Parser::from(&source).collect::<Result<Vec<_>, _>>()?;
+    let (reader, writer) = pipe();
+    let (io, file) = match direction {
+        ProcessDirection::Read => (
+            Io {
+                stdout: OutStream::PipeWriter(writer),
+                ..Io::default()
+            },
+            File::from(OwnedFd::from(reader)),
+        ),
+        ProcessDirection::Write => (
+            Io {
+                stdin: InStream::PipeReader(reader),
+                ..Io::default()
+            },
+            File::from(OwnedFd::from(writer)),
+        ),
+    };
+    let child = spawn_subshell(None, io.raw_fds(), || {
+        // those of the word being expanded belong to its command, not to the commands substituted
+        PROCESS_SUBSTITUTIONS.take();
+        execute_lists(&lists, &io)
+    })?;
+    // reaped once it finishes, as nothing waits for it
+    JOBS.lock().unwrap().detach(child);
+
+    let path = format!("/dev/fd/{}", file.as_raw_fd());
+    PROCESS_SUBSTITUTIONS.with_borrow_mut(|substitutions| substitutions.push(file));
+    Ok(path)
+}
+
+/// runs every command of `lists`, returning the status of the last one
+fn execute_lists(lists: &[List], io: &Io) -> io::Result<ExitStatus> {
+    let mut exit_status = ExitStatus::default();
+    for list in lists {
+        exit_status = list.execute_with(io)?;
+    }
+    Ok(exit_status)
+}
+
+fn get_variable(name: &str) -> Option<String> {
+    VARIABLES.read().unwrap().get(name).map(str::to_string)
+}
+
+/// the value of a variable, positional or special parameter; `None` if it is unset
+fn parameter_value(name: &str) -> Option<String> {
+    if is_valid_name(name) {
+        return get_variable(name);
+    }
+    let variables = VARIABLES.read().unwrap();
+    match name {
+        "0" => Some(variables.shell_name.clone()),
+        "?" => Some(exit_code(variables.last_exit_status).to_string()),
+        "$" => Some(variables.shell_pid.to_string()),
+        "!" => Some(JOBS.lock().unwrap().last_background_pid?.to_string()),
+        "#" => Some(variables.positional_parameters.len().to_string()),
+        // only the options this shell implements
+        "-" => {
+            let mut options = String::new();
+            if variables.noclobber {
+                options.push('C');
+            }
+            if jobs::job_control_enabled() {
+                options.push_str("im");
+            }
+            Some(options)
+        }
+        "@" | "*" => Some(join_positional(&variables)),
+        number => {
+            let index = number.parse::<usize>().ok()?.checked_sub(1)?;
+            variables.positional_parameters.get(index).cloned()
+        }
+    }
+}
+
+/// "Expands to the positional parameters, starting from one."
+///
+/// `"$*"` joins them into one field, `"$@"` keeps every parameter in its own field.
+/// Unquoted, both are split into fields like any other expansion.
+fn expand_positional(star: bool, in_double_quotes: bool) -> Vec<Piece> {
+    let variables = VARIABLES.read().unwrap();
+    let parameters = &variables.positional_parameters;
+    if star && in_double_quotes {
+        return vec![Piece::expanded(join_positional(&variables), true)];
+    }
+    if parameters.is_empty() {
+        // unquoted and empty, so it adds nothing to a field
+        return vec![Piece::expanded("", false)];
+    }
+    parameters
+        .iter()
+        .map(|parameter| vec![Piece::expanded(parameter.clone(), in_double_quotes)])
+        .collect::<Vec<_>>()
+        .join(&Piece::field_break())
+}
+
+/// Joins the positional parameters with the first character of `IFS`, as `"$*"` does.
+///
+/// Takes the table its caller already holds: locking it a second time could wait behind a writer forever.
+fn join_positional(variables: &VariableTable) -> String {
+    let separator = match variables.get("IFS") {
+        Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+        None => " ".to_string(),
+    };
+    variables.positional_parameters.join(&separator)
+}
+
+/// expands a parameter, applying its operation if it has one
+fn expand_parameter(
+    parameter: &ParameterExpansion,
+    in_double_quotes: bool,
+) -> Result<Vec<Piece>, ExpansionError> {
+    let name = parameter.name.as_str();
+    let expanded = |text: String| Piece::expanded(text, in_double_quotes);
+
+    let Some(operation) = &parameter.operation else {
+        return Ok(match name {
+            "@" | "*" => expand_positional(name == "*", in_double_quotes),
+            _ => vec![expanded(parameter_value(name).unwrap_or_default())],
+        });
+    };
+    let value = parameter_value(name);
+
+    // the word following an operator is expanded only when it is used
+    let word_pieces = |word: &Word| -> Result<Vec<Piece>, ExpansionError> {
+        Ok(
+            expand_parts(&word.parts, in_double_quotes, TildePrefixes::AtStart)?
+                .into_iter()
+                .map(|piece| Piece {
+                    expanded: true,
+                    ..piece
+                })
+                .collect(),
+        )
+    };
+
+    match operation {
+        ParameterOperation::Length => {
+            let length = match name {
+                "@" | "*" => VARIABLES.read().unwrap().positional_parameters.len(),
+                _ => value.unwrap_or_default().chars().count(),
+            };
+            Ok(vec![expanded(length.to_string())])
+        }
+        ParameterOperation::Conditional {
+            operator,
+            null_is_unset,
+            word,
+        } => {
+            let is_set = value
+                .as_ref()
+                .is_some_and(|value| !(*null_is_unset && value.is_empty()));
+            match operator {
+                ConditionalOperator::UseAlternative if is_set => word_pieces(word),
+                ConditionalOperator::UseAlternative => Ok(vec![]),
+                _ if is_set => Ok(vec![expanded(value.unwrap_or_default())]),
+                ConditionalOperator::UseDefault => word_pieces(word),
+                ConditionalOperator::AssignDefault => {
+                    let assigned =
+                        expand_to_string(word, in_double_quotes, TildePrefixes::AtStart)?;
+                    VARIABLES.write().unwrap().set(name, assigned.clone())?;
+                    Ok(vec![expanded(assigned)])
+                }
+                ConditionalOperator::ErrorIfUnset => {
+                    let message =
+                        match expand_to_string(word, in_double_quotes, TildePrefixes::AtStart)? {
+                            message if message.is_empty() => {
+                                "parameter null or not set".to_string()
+                            }
+                            message => message,
+                        };
+                    Err(ExpansionError::NullOrUnset(name.to_string(), message))
+                }
+            }
+        }
+        ParameterOperation::RemovePrefix { longest, pattern } => {
+            let value = value.unwrap_or_default();
+            let removed = remove_prefix(&value, &expand_to_pattern(pattern)?, *longest);
+            Ok(vec![expanded(removed.to_string())])
+        }
+        ParameterOperation::RemoveSuffix { longest, pattern } => {
+            let value = value.unwrap_or_default();
+            let removed = remove_suffix(&value, &expand_to_pattern(pattern)?, *longest);
+            Ok(vec![expanded(removed.to_string())])
+        }
+        ParameterOperation::Replace {
+            mode,
+            pattern,
+            replacement,
+        } => {
+            let replacement = match replacement {
+                Some(replacement) => {
+                    expand_to_string(replacement, in_double_quotes, TildePrefixes::AtStart)?
+                }
+                None => String::new(),
+            };
+            let value = value.unwrap_or_default();
+            Ok(vec![expanded(replace(
+                &value,
+                *mode,
+                pattern,
+                &replacement,
+            )?)])
+        }
+        ParameterOperation::Substring { offset, length } => {
+            let value = value.unwrap_or_default();
+            Ok(vec![expanded(substring(&value, offset, length.as_ref())?)])
+        }
+    }
+}
+
+/// removes the shortest (or longest) prefix of `value` matching `pattern`
+fn remove_prefix<'a>(value: &'a str, pattern: &Pattern, longest: bool) -> &'a str {
+    let mut boundaries: Vec<usize> = value
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([value.len()])
+        .collect();
+    if longest {
+        boundaries.reverse();
+    }
+    boundaries
+        .into_iter()
+        .find(|&end| pattern.matches(&value[..end]))
+        .map_or(value, |end| &value[end..])
+}
+
+/// removes the shortest (or longest) suffix of `value` matching `pattern`
+fn remove_suffix<'a>(value: &'a str, pattern: &Pattern, longest: bool) -> &'a str {
+    let mut boundaries: Vec<usize> = value
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([value.len()])
+        .collect();
+    if !longest {
+        boundaries.reverse();
+    }
+    boundaries
+        .into_iter()
+        .find(|&start| pattern.matches(&value[start..]))
+        .map_or(value, |start| &value[..start])
+}
+
+/// `${NAME/pattern/replacement}`
+fn replace(
+    value: &str,
+    mode: ReplaceMode,
+    pattern_word: &Word,
+    replacement: &str,
+) -> Result<String, ExpansionError> {
+    if pattern_word.text.is_empty() {
+        return Ok(value.to_string());
+    }
+    let pattern = expand_to_pattern(pattern_word)?;
+
+    let boundaries: Vec<usize> = value
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([value.len()])
+        .collect();
+    let longest_match_from = |start: usize| {
+        boundaries
+            .iter()
+            .rev()
+            .copied()
+            .take_while(|&end| end >= start)
+            .find(|&end| pattern.matches(&value[start..end]))
+    };
+
+    match mode {
+        ReplaceMode::Prefix => Ok(match longest_match_from(0) {
+            Some(end) => format!("{replacement}{}", &value[end..]),
+            None => value.to_string(),
+        }),
+        ReplaceMode::Suffix => Ok(
+            match boundaries
+                .iter()
+                .find(|&&start| pattern.matches(&value[start..]))
+            {
+                Some(&start) => format!("{}{replacement}", &value[..start]),
+                None => value.to_string(),
+            },
+        ),
+        ReplaceMode::First | ReplaceMode::All => {
+            let mut result = String::new();
+            let mut position = 0;
+            let mut replaced = false;
+            while position < value.len() {
+                match longest_match_from(position).filter(|_| mode == ReplaceMode::All || !replaced)
+                {
+                    Some(end) if end > position => {
+                        result.push_str(replacement);
+                        position = end;
+                        replaced = true;
+                    }
+                    _ => {
+                        let c = value[position..].chars().next().expect("before the end");
+                        result.push(c);
+                        position += c.len_utf8();
+                    }
+                }
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// `${NAME:offset}` and `${NAME:offset:length}`, counted in characters.
+///
+/// Both are arithmetic expressions; a negative one counts back from the end of the value.
+fn substring(value: &str, offset: &Word, length: Option<&Word>) -> Result<String, ExpansionError> {
+    let evaluate = |word: &Word| -> Result<i64, ExpansionError> {
+        Ok(arithmetic::evaluate(&expand_arithmetic_expression(word)?)?)
+    };
+    let char_count = i64::try_from(value.chars().count()).unwrap_or(i64::MAX);
+
+    let mut start = evaluate(offset)?;
+    if start < 0 {
+        start += char_count;
+    }
+    if !(0..=char_count).contains(&start) {
+        return Ok(String::new());
+    }
+    let end = match length.map(evaluate).transpose()? {
+        None => char_count,
+        Some(length) if length < 0 => {
+            let end = char_count + length;
+            if end < start {
+                return Err(ExpansionError::NegativeSubstring(length.to_string()));
+            }
+            end
+        }
+        Some(length) => start.saturating_add(length).min(char_count),
+    };
+
+    let (start, end) = (start as usize, end as usize);
+    Ok(value.chars().skip(start).take(end - start).collect())
+}
+
+/// "The shell scans the results of parameter expansion, command substitution, and arithmetic expansion
+/// that did not occur within double quotes for word splitting."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Word-Splitting)
+fn split_fields(pieces: &[Piece]) -> Vec<Field> {
+    let ifs = VARIABLES
+        .read()
+        .unwrap()
+        .get("IFS")
+        .map_or_else(|| " \t\n".to_string(), str::to_string);
+
+    let mut fields = Vec::new();
+    let mut current = Field::default();
+    // whether `current` is a field even if it is empty
+    let mut has_field = false;
+
+    for piece in pieces {
+        if piece.field_break {
+            if has_field {
+                fields.push(std::mem::take(&mut current));
+                has_field = false;
+            }
+            continue;
+        }
+        if piece.quoted || !piece.expanded {
+            current.push_str(&piece.text, piece.quoted);
+            has_field |= piece.quoted || !piece.text.is_empty();
+            continue;
+        }
+
+        let mut chars = piece.text.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !ifs.contains(c) {
+                current.push(c, false);
+                has_field = true;
+                continue;
+            }
+            // whitespace around a separator is part of it,
+            // and a non-whitespace separator delimits a field on its own, even an empty one
+            let is_ifs_whitespace = |c: &char| ifs.contains(*c) && c.is_whitespace();
+            while chars.next_if(is_ifs_whitespace).is_some() {}
+            let mut delimits_empty = !c.is_whitespace();
+            if !delimits_empty && chars.next_if(|c| ifs.contains(*c)).is_some() {
+                delimits_empty = true;
+                while chars.next_if(is_ifs_whitespace).is_some() {}
+            }
+            if has_field || delimits_empty {
+                fields.push(std::mem::take(&mut current));
+                has_field = false;
+            }
+        }
+    }
+    if has_field {
+        fields.push(current);
+    }
+    fields
+}
+
+/// A word after field splitting, before pathname expansion.
+#[derive(Debug, Default)]
+struct Field {
+    value: String,
+    /// the value with its quoted characters escaped, so only the unquoted ones are special
+    pattern: String,
+    /// whether an unquoted `*`, `?` or `[` makes the field subject to pathname expansion
+    has_wildcards: bool,
+}
+
+impl Field {
+    fn push(&mut self, c: char, quoted: bool) {
+        self.value.push(c);
+        if quoted {
+            self.pattern
+                .push_str(&pattern::escape(c.encode_utf8(&mut [0; 4])));
+        } else {
+            self.pattern.push(c);
+            self.has_wildcards |= matches!(c, '*' | '?' | '[');
+        }
+    }
+
+    fn push_str(&mut self, text: &str, quoted: bool) {
+        for c in text.chars() {
+            self.push(c, quoted);
+        }
+    }
+
+    /// "If one of these characters appears, and is not quoted, then the word is regarded as a pattern,
+    /// and replaced with a sorted list of filenames matching the pattern.
+    /// If no matching filenames are found [...] the word is left unchanged."
+    ///
+    /// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Filename-Expansion)
+    fn expand_pathnames(self) -> Vec<String> {
+        if !self.has_wildcards {
+            return vec![self.value];
+        }
+        match glob::expand(&self.pattern) {
+            matches if matches.is_empty() => vec![self.value],
+            matches => matches,
+        }
+    }
+}
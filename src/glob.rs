@@ -0,0 +1,68 @@
+//! "After word splitting, unless the -f option has been set, Bash scans each word for the characters
+//! ‘*’, ‘?’, and ‘[’. If one of these characters appears, and is not quoted, then the word is regarded as a pattern."
+//!
+//! -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Filename-Expansion)
+
+use crate::pattern::{self, Pattern};
+use std::path::Path;
+
+/// Every existing path matching `pattern`, sorted.
+///
+/// The pattern is matched one `/` separated component at a time, against the entries of the directories
+/// matched so far. Names starting with `.` only match a component that starts with a literal `.`
+pub fn expand(pattern: &str) -> Vec<String> {
+    let (mut paths, relative_pattern) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+
+    let mut components = relative_pattern.split('/').peekable();
+    while let Some(component) = components.next() {
+        if component.is_empty() {
+            // a trailing slash only matches directories, repeated slashes mean nothing
+            if components.peek().is_none() {
+                paths.retain(|path| Path::new(path).is_dir());
+                paths.iter_mut().for_each(|path| path.push('/'));
+            }
+            continue;
+        }
+
+        paths = if pattern::has_wildcards(component) {
+            let matcher = Pattern::parse(component);
+            let show_hidden = component.starts_with('.') || component.starts_with("\\.");
+            paths
+                .iter()
+                .flat_map(|path| matching_entries(path, &matcher, show_hidden))
+                .collect()
+        } else {
+            let name = pattern::unescape(component);
+            paths.iter().map(|path| join(path, &name)).collect()
+        };
+    }
+
+    paths.retain(|path| Path::new(path).symlink_metadata().is_ok());
+    paths.sort();
+    paths
+}
+
+/// the entries of the directory at `path` whose name matches
+fn matching_entries(path: &str, matcher: &Pattern, show_hidden: bool) -> Vec<String> {
+    let directory = if path.is_empty() { "." } else { path };
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| show_hidden || !name.starts_with('.'))
+        .filter(|name| matcher.matches(name))
+        .map(|name| join(path, &name))
+        .collect()
+}
+
+fn join(path: &str, name: &str) -> String {
+    if path.is_empty() || path.ends_with('/') {
+        format!("{path}{name}")
+    } else {
+        format!("{path}/{name}")
+    }
+}
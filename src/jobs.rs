@@ -0,0 +1,499 @@
+use crate::commands::{stopped_exit_status, ChildHandle, ChildState, ProcessGroup};
+use crate::variables::VARIABLES;
+use nix::sys::signal::{killpg, signal, SigHandler, Signal};
+use nix::sys::termios::{tcgetattr, tcsetattr, SetArg, Termios};
+use nix::unistd::{getpgrp, getpid, isatty, setpgid, tcgetpgrp, tcsetpgrp, Pid};
+use std::cell::Cell;
+use std::fmt::Display;
+use std::io::{self, stdin, Write};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::{LazyLock, Mutex, OnceLock};
+
+/// every job that was started in the background or stopped
+pub static JOBS: LazyLock<Mutex<JobTable>> = LazyLock::new(Mutex::default);
+
+static JOB_CONTROL: OnceLock<JobControl> = OnceLock::new();
+
+/// signals the terminal would otherwise send to the shell itself, stopping or killing it.
+/// Ignored by the shell, but not by the jobs it starts
+const IGNORED_SIGNALS: [Signal; 5] = [
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGTTIN,
+    Signal::SIGTTOU,
+];
+
+thread_local! {
+    /// `true` in a forked copy of the shell, where job control stays with the shell it was forked from
+    static IN_SUBSHELL: Cell<bool> = const { Cell::new(false) };
+}
+
+/// "A set of processes comprising a pipeline, and any processes descended from it,
+/// that are all in the same process group."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-job)
+pub struct Job {
+    pub id: usize,
+    /// the command line that started the job, as shown to the user
+    pub command: String,
+    pub state: JobState,
+    handle: ChildHandle,
+    /// terminal settings the job was using when it got stopped in the foreground
+    terminal_modes: Option<Termios>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+}
+
+#[derive(Default)]
+pub struct JobTable {
+    /// ordered from the least to the most recently started or stopped job
+    jobs: Vec<Job>,
+    /// the value of `$!`
+    pub last_background_pid: Option<u32>,
+    /// processes that are no job, such as those of a process substitution, only kept to be reaped
+    detached: Vec<ChildHandle>,
+}
+
+/// The terminal and process group of an interactive shell.
+/// Only exists once [`enable_job_control`] succeeded.
+struct JobControl {
+    terminal: OwnedFd,
+    shell_process_group: Pid,
+    /// kept in its `libc` form because [`Termios`] is not [`Sync`]
+    shell_terminal_modes: nix::libc::termios,
+}
+
+impl Job {
+    fn new(command: String, handle: ChildHandle) -> Self {
+        Self {
+            id: 0,
+            command,
+            state: JobState::Running,
+            handle,
+            terminal_modes: None,
+        }
+    }
+
+    fn continue_running(&mut self) -> io::Result<()> {
+        if let Some(process_group) = self.handle.process_group() {
+            killpg(process_group, Signal::SIGCONT)?;
+        }
+        self.state = JobState::Running;
+        Ok(())
+    }
+
+    fn status_line(&self, marker: char, show_pid: bool) -> String {
+        let state = match self.state {
+            JobState::Running => "Running",
+            JobState::Stopped => "Stopped",
+        };
+        let suffix = if self.state == JobState::Running {
+            " &"
+        } else {
+            ""
+        };
+        let pid = match self.handle.process_group() {
+            Some(pid) if show_pid => format!(" {pid} "),
+            _ => "  ".to_string(),
+        };
+        format!(
+            "[{}]{marker}{pid}{state:<24}{}{suffix}",
+            self.id, self.command
+        )
+    }
+}
+
+impl JobTable {
+    /// registers a job that has been started in the background
+    pub fn add(&mut self, command: String, handle: ChildHandle) -> &Job {
+        if let Some(pid) = handle.pid() {
+            self.last_background_pid = Some(pid);
+        }
+        self.insert(Job::new(command, handle))
+    }
+
+    /// keeps a process nothing waits for until it can be reaped, reaping those that finished meanwhile
+    pub fn detach(&mut self, handle: ChildHandle) {
+        self.reap_detached();
+        self.detached.push(handle);
+    }
+
+    fn reap_detached(&mut self) {
+        self.detached
+            .retain_mut(|handle| !matches!(handle.try_wait(), Ok(ChildState::Exited(_)) | Err(_)));
+    }
+
+    /// puts the job at the end of the table, giving it an id if it does not have one yet
+    fn insert(&mut self, mut job: Job) -> &Job {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        self.jobs.push(job);
+        self.jobs.last().expect("just pushed")
+    }
+
+    /// removes every finished job from the table, returning them with their exit status
+    pub fn reap(&mut self) -> io::Result<Vec<(Job, ExitStatus)>> {
+        self.reap_detached();
+        let mut finished = Vec::new();
+        let mut index = 0;
+        while index < self.jobs.len() {
+            match self.jobs[index].handle.try_wait()? {
+                ChildState::Exited(exit_status) => {
+                    finished.push((self.jobs.remove(index), exit_status));
+                    continue;
+                }
+                ChildState::Stopped => self.jobs[index].state = JobState::Stopped,
+                ChildState::Running => {}
+            }
+            index += 1;
+        }
+        Ok(finished)
+    }
+
+    /// ids of the current and the previous job
+    fn current_and_previous(&self) -> (Option<usize>, Option<usize>) {
+        let mut ids = self.jobs.iter().rev().map(|job| job.id);
+        (ids.next(), ids.next())
+    }
+
+    /// one line per job, as printed by `jobs`
+    pub fn status_lines(&self, show_pid: bool) -> Vec<String> {
+        let current_and_previous = self.current_and_previous();
+        self.jobs
+            .iter()
+            .map(|job| job.status_line(marker(job.id, current_and_previous), show_pid))
+            .collect()
+    }
+
+    /// process group ids of every job, as printed by `jobs -p`
+    pub fn process_groups(&self) -> Vec<Pid> {
+        self.jobs
+            .iter()
+            .filter_map(|job| job.handle.process_group())
+            .collect()
+    }
+
+    /// Finds the job `spec` refers to:
+    /// `%n` or `n` for job number n, `%+`, `%%` or `%` for the current job, `%-` for the previous job,
+    /// `%name` for the job whose command starts with name and `%?name` for the job whose command contains it.
+    ///
+    /// `None` means the current job. The error is a message for the user.
+    fn position(&self, spec: Option<&str>) -> Result<usize, String> {
+        let (current, previous) = self.current_and_previous();
+        let spec = spec.unwrap_or("%+");
+        let pattern = spec.strip_prefix('%').unwrap_or(spec);
+
+        let id = if let Ok(number) = pattern.parse() {
+            number
+        } else {
+            match pattern {
+                "" | "+" | "%" => current.ok_or("current: no such job")?,
+                "-" => previous.ok_or("previous: no such job")?,
+                name => {
+                    let matches = |job: &&Job| match name.strip_prefix('?') {
+                        Some(contained) => job.command.contains(contained),
+                        None => job.command.starts_with(name),
+                    };
+                    let mut matching = self.jobs.iter().filter(matches);
+                    match (matching.next(), matching.next()) {
+                        (Some(job), None) => job.id,
+                        (Some(_), Some(_)) => return Err(format!("{spec}: ambiguous job spec")),
+                        (None, _) => return Err(format!("{spec}: no such job")),
+                    }
+                }
+            }
+        };
+
+        self.jobs
+            .iter()
+            .position(|job| job.id == id)
+            .ok_or_else(|| format!("{spec}: no such job"))
+    }
+
+    /// removes the job `spec` refers to from the table
+    pub fn take(&mut self, spec: Option<&str>) -> Result<Job, String> {
+        let position = self.position(spec)?;
+        Ok(self.jobs.remove(position))
+    }
+
+    /// Removes the job `spec` refers to from the table, to continue it in the foreground.
+    ///
+    /// A job without a process group is left in the table: the terminal can not be handed to it,
+    /// so Ctrl-C and Ctrl-Z could never reach it.
+    pub fn take_for_foreground(&mut self, spec: Option<&str>) -> Result<Job, String> {
+        if job_control().is_none() {
+            return Err("no job control".to_string());
+        }
+        let position = self.position(spec)?;
+        let job = &self.jobs[position];
+        if job.handle.process_group().is_none() {
+            return Err(format!("job {} has no process group", job.id));
+        }
+        Ok(self.jobs.remove(position))
+    }
+
+    /// removes the job containing the process `pid` from the table
+    pub fn take_by_pid(&mut self, pid: u32) -> Option<Job> {
+        let position = self.jobs.iter().position(|job| {
+            job.handle.pid() == Some(pid)
+                || job.handle.process_group() == Some(Pid::from_raw(pid.cast_signed()))
+        })?;
+        Some(self.jobs.remove(position))
+    }
+
+    /// removes every job from the table
+    pub fn take_all(&mut self) -> Vec<Job> {
+        std::mem::take(&mut self.jobs)
+    }
+
+    /// continues a stopped job without waiting for it, returning the line to show the user
+    pub fn continue_in_background(&mut self, spec: Option<&str>) -> Result<String, String> {
+        if job_control().is_none() {
+            return Err("no job control".to_string());
+        }
+        let position = self.position(spec)?;
+        let job = &mut self.jobs[position];
+        if job.state == JobState::Running {
+            return Err(format!("job {} already in background", job.id));
+        }
+        job.continue_running().map_err(|e| e.to_string())?;
+        let job = self.jobs.remove(position);
+        let line = format!("[{}]+ {} &", job.id, job.command);
+        self.insert(job);
+        Ok(line)
+    }
+}
+
+/// `+` for the current job, `-` for the previous job, otherwise a blank
+fn marker(id: usize, (current, previous): (Option<usize>, Option<usize>)) -> char {
+    if current == Some(id) {
+        '+'
+    } else if previous == Some(id) {
+        '-'
+    } else {
+        ' '
+    }
+}
+
+/// writes a line for each background job that has finished since the last call
+pub fn report_finished_jobs(mut writer: impl Write) -> io::Result<()> {
+    let mut jobs = JOBS.lock().unwrap();
+    // markers are decided before the finished jobs leave the table
+    let current_and_previous = jobs.current_and_previous();
+    let finished = jobs.reap()?;
+    drop(jobs);
+
+    for (job, exit_status) in finished {
+        let marker = marker(job.id, current_and_previous);
+        let state = match exit_status.code() {
+            Some(0) => "Done".to_string(),
+            Some(code) => format!("Exit {code}"),
+            None => "Terminated".to_string(),
+        };
+        writeln!(writer, "[{}]{marker}  {state:<24}{}", job.id, job.command)?;
+    }
+    Ok(())
+}
+
+/// Puts the shell in its own process group in the foreground of the terminal,
+/// so that every pipeline can be given a process group of its own.
+///
+/// Does nothing unless standard input is a terminal.
+pub fn enable_job_control() -> io::Result<()> {
+    let terminal = stdin();
+    if !isatty(terminal.as_raw_fd())? {
+        return Ok(());
+    }
+
+    // wait until the user puts the shell in the foreground
+    while tcgetpgrp(terminal.as_fd())? != getpgrp() {
+        killpg(getpgrp(), Signal::SIGTTIN)?;
+    }
+
+    for ignored_signal in IGNORED_SIGNALS {
+        // SAFETY: no handler function is installed
+        unsafe { signal(ignored_signal, SigHandler::SigIgn) }?;
+    }
+
+    let shell_process_group = getpid();
+    if getpgrp() != shell_process_group {
+        setpgid(shell_process_group, shell_process_group)?;
+    }
+    tcsetpgrp(terminal.as_fd(), shell_process_group)?;
+
+    let job_control = JobControl {
+        terminal: terminal.as_fd().try_clone_to_owned()?,
+        shell_process_group,
+        shell_terminal_modes: tcgetattr(terminal.as_fd())?.into(),
+    };
+    _ = JOB_CONTROL.set(job_control);
+    log::debug!("job control enabled with process group {shell_process_group}");
+    Ok(())
+}
+
+/// the job control of the shell, `None` if not enabled or in a subshell
+fn job_control() -> Option<&'static JobControl> {
+    JOB_CONTROL.get().filter(|_| !IN_SUBSHELL.get())
+}
+
+/// how the processes of a new job are grouped, or `None` if job control is not enabled
+pub fn new_process_group(foreground: bool) -> Option<ProcessGroup> {
+    job_control()?;
+    Some(ProcessGroup {
+        id: None,
+        foreground,
+    })
+}
+
+pub fn job_control_enabled() -> bool {
+    job_control().is_some()
+}
+
+/// `true` in a forked copy of the shell
+pub fn in_subshell() -> bool {
+    IN_SUBSHELL.get()
+}
+
+/// Sets up a freshly forked copy of the shell, after it joined its process group.
+///
+/// The commands it runs stay in that group, where the signals of the terminal reach them,
+/// and the jobs of the shell are not its children to wait for.
+pub fn enter_subshell() {
+    IN_SUBSHELL.set(true);
+    // like any process writing to a pipe, it ends once the reader goes away, rather than the shell's `EPIPE`
+    let mut default_signals = vec![Signal::SIGPIPE];
+    if JOB_CONTROL.get().is_some() {
+        default_signals.extend(IGNORED_SIGNALS);
+    }
+    for default_signal in default_signals {
+        // SAFETY: no handler function is installed
+        let _ = unsafe { signal(default_signal, SigHandler::SigDfl) };
+    }
+    JOBS.lock().unwrap().take_all();
+    VARIABLES.write().unwrap().interactive = false;
+}
+
+/// Runs in a freshly forked job process, just before `exec`.
+///
+/// Must only make async-signal-safe calls.
+pub fn prepare_job_process(take_terminal: bool) -> io::Result<()> {
+    if take_terminal {
+        if let Some(job_control) = JOB_CONTROL.get() {
+            // done here as well as in the shell, so the process never reads from the terminal before owning it
+            tcsetpgrp(&job_control.terminal, getpgrp())?;
+        }
+    }
+    for ignored_signal in IGNORED_SIGNALS {
+        // SAFETY: no handler function is installed
+        unsafe { signal(ignored_signal, SigHandler::SigDfl) }?;
+    }
+    Ok(())
+}
+
+impl JobControl {
+    fn give_terminal_to(&self, process_group: Pid, modes: Option<&Termios>) {
+        if let Some(modes) = modes {
+            if let Err(e) = tcsetattr(&self.terminal, SetArg::TCSADRAIN, modes) {
+                log::warn!("failed to restore terminal modes of job: {e}");
+            }
+        }
+        if let Err(e) = tcsetpgrp(&self.terminal, process_group) {
+            log::warn!("failed to give terminal to process group {process_group}: {e}");
+        }
+    }
+
+    /// returns the terminal modes the job left behind
+    fn take_back_terminal(&self) -> Option<Termios> {
+        if let Err(e) = tcsetpgrp(&self.terminal, self.shell_process_group) {
+            log::error!("failed to take back the terminal: {e}");
+        }
+        let job_modes = tcgetattr(&self.terminal).ok();
+        let shell_modes = Termios::from(self.shell_terminal_modes);
+        if let Err(e) = tcsetattr(&self.terminal, SetArg::TCSADRAIN, &shell_modes) {
+            log::warn!("failed to restore terminal modes of the shell: {e}");
+        }
+        job_modes
+    }
+}
+
+/// Gives the terminal back to the shell after a job process failed to start,
+/// as it may have taken the terminal just before.
+pub fn reclaim_terminal() {
+    if let Some(job_control) = job_control() {
+        job_control.take_back_terminal();
+    }
+}
+
+/// Waits for a freshly spawned pipeline, with the terminal handed to it if job control is enabled.
+///
+/// If it gets stopped it is moved to the job table instead.
+pub fn wait_for_foreground(
+    mut handle: ChildHandle,
+    command: impl Display,
+) -> io::Result<ExitStatus> {
+    if job_control().is_none() {
+        return handle.wait();
+    }
+    wait_with_terminal(Job::new(command.to_string(), handle), false)
+}
+
+/// continues a job in the foreground and waits for it
+pub fn continue_in_foreground(job: Job) -> io::Result<ExitStatus> {
+    wait_with_terminal(job, true)
+}
+
+/// waits for `job` to finish; a job stopped in the meantime is put in the job table
+fn wait_with_terminal(mut job: Job, continue_job: bool) -> io::Result<ExitStatus> {
+    let control = job_control().zip(job.handle.process_group());
+    if let Some((job_control, process_group)) = control {
+        job_control.give_terminal_to(process_group, job.terminal_modes.as_ref());
+    }
+    if continue_job {
+        job.continue_running()?;
+    }
+
+    let state = job.handle.wait_or_stop();
+
+    // even without a process group of its own, a process of the job may have taken the terminal before failing
+    if let Some(job_control) = job_control() {
+        job.terminal_modes = job_control.take_back_terminal();
+    }
+
+    match state? {
+        ChildState::Exited(exit_status) => {
+            if control.is_some() && exit_status.signal() == Some(Signal::SIGINT as i32) {
+                // the terminal echoed `^C` without ending the line
+                eprintln!();
+            }
+            Ok(exit_status)
+        }
+        ChildState::Stopped | ChildState::Running => {
+            job.state = JobState::Stopped;
+            let mut jobs = JOBS.lock().unwrap();
+            let line = jobs.insert(job).status_line('+', false);
+            drop(jobs);
+            eprintln!();
+            eprintln!("{line}");
+            Ok(stopped_exit_status())
+        }
+    }
+}
+
+/// waits for a job without giving it the terminal, as done by `wait`
+pub fn wait_for_job(mut job: Job) -> io::Result<ExitStatus> {
+    if job.state == JobState::Stopped {
+        // a stopped job would never finish on its own
+        let mut jobs = JOBS.lock().unwrap();
+        jobs.insert(job);
+        return Ok(stopped_exit_status());
+    }
+    job.handle.wait()
+}
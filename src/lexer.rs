@@ -0,0 +1,630 @@
+use crate::tokens::{
+    is_shell_blank, ConditionalOperator, ControlOperator, HereDocument, Operator,
+    ParameterExpansion, ParameterOperation, ProcessDirection, RedirectOperator, ReplaceMode, Span,
+    Token, Word, WordPart, WordPartKind,
+};
+
+/// Splits a line of input into [`Token`]s.
+///
+/// Each word is split into the [parts][WordPart] that make it up, which keep their byte offsets into the line.
+/// Quotes are removed later, during [expansion][crate::expansion].
+#[derive(Clone, Debug)]
+pub struct TokenStream<'a> {
+    input: &'a str,
+    /// byte offset of the next token
+    position: usize,
+    /// `Some` after `<<` or `<<-`: the next word is the delimiter of a here-document, tabs are stripped if `true`
+    pending_here_document: Option<bool>,
+    /// where to continue after the end of the current line, past the here-documents already read
+    here_documents_end: Option<usize>,
+    /// false while only looking for the end of the line, so that no here-document gets read
+    reads_here_documents: bool,
+}
+
+impl<'a, T: AsRef<str>> From<&'a T> for TokenStream<'a> {
+    fn from(value: &'a T) -> Self {
+        Self {
+            input: value.as_ref(),
+            position: 0,
+            pending_here_document: None,
+            here_documents_end: None,
+            reads_here_documents: true,
+        }
+    }
+}
+
+impl Iterator for TokenStream<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = &self.input[self.position..];
+        self.position += rest.len() - rest.trim_start_matches(is_shell_blank).len();
+        let start = self.position;
+        if start == self.input.len() {
+            return None;
+        }
+
+        if let Some(length) = io_number_length(&self.input[start..]) {
+            if let Ok(fd) = self.input[start..start + length].parse() {
+                self.position += length;
+                return Some(Token::IoNumber(fd));
+            }
+        }
+        // `((` starting a word is an arithmetic command, not two subshells,
+        // `<(` and `>(` a process substitution rather than a redirection
+        if !starts_with_substitution(&self.input[start..]) {
+            if let Some(length) = operator_length(&self.input[start..]) {
+                self.position += length;
+                let operator = self.input[start..self.position]
+                    .parse()
+                    .expect("operator_length only measures operators");
+                self.pending_here_document = match operator {
+                    Operator::Redirect(RedirectOperator::HereDocument) => Some(false),
+                    Operator::Redirect(RedirectOperator::HereDocumentStripTabs) => Some(true),
+                    _ => None,
+                };
+                if let Operator::Control(ControlOperator::Newline) = operator {
+                    // the lines of the here-documents were already read
+                    if let Some(end) = self.here_documents_end.take() {
+                        self.position = end;
+                    }
+                }
+                return Some(Token::Operator(operator));
+            }
+        }
+        self.position = word_end(self.input, start);
+        let word = Word::parse(&self.input[start..self.position], start);
+        match self.pending_here_document.take() {
+            Some(strip_tabs) if self.reads_here_documents => Some(Token::HereDocument(
+                self.read_here_document(word, strip_tabs),
+            )),
+            _ => Some(Token::Word(word)),
+        }
+    }
+}
+
+impl TokenStream<'_> {
+    /// Reads the lines of the here-document `delimiter` introduces, which start on the line after the current one,
+    /// or after the previous here-document on it.
+    fn read_here_document(&mut self, delimiter: Word, strip_tabs: bool) -> HereDocument {
+        let body_start = match self.here_documents_end {
+            Some(end) => end,
+            None => self.line_end(),
+        };
+        let (delimiter_text, quoted) = remove_quotes(&delimiter);
+
+        let mut body = String::new();
+        let mut end = self.input.len();
+        let mut terminated = false;
+        let mut line_start = body_start;
+        while line_start < self.input.len() {
+            let line_end = self.input[line_start..]
+                .find('\n')
+                .map_or(self.input.len(), |index| line_start + index + 1);
+            let line = self.input[line_start..line_end].trim_end_matches('\n');
+            let line = if strip_tabs {
+                line.trim_start_matches('\t')
+            } else {
+                line
+            };
+            line_start = line_end;
+            if line == delimiter_text {
+                end = line_end;
+                terminated = true;
+                break;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+        self.here_documents_end = Some(end);
+
+        let body = if quoted {
+            Word {
+                span: body_start..body_start + body.len(),
+                parts: vec![WordPart {
+                    kind: WordPartKind::Literal(body.clone()),
+                    span: body_start..body_start + body.len(),
+                }],
+                text: body,
+            }
+        } else {
+            Word::parse_here_document(&body, body_start)
+        };
+        HereDocument {
+            delimiter,
+            body,
+            terminated,
+        }
+    }
+
+    /// the index just past the newline ending the current line, or the end of input
+    fn line_end(&self) -> usize {
+        let mut rest = Self {
+            pending_here_document: None,
+            here_documents_end: None,
+            reads_here_documents: false,
+            ..self.clone()
+        };
+        while let Some(token) = rest.next() {
+            if let Token::Operator(Operator::Control(ControlOperator::Newline)) = token {
+                return rest.position;
+            }
+        }
+        self.input.len()
+    }
+}
+
+/// the text of a here-document delimiter after quote removal, and whether any part of it was quoted
+fn remove_quotes(delimiter: &Word) -> (String, bool) {
+    let mut text = String::new();
+    let mut quoted = false;
+    let mut chars = delimiter.text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' => quoted = true,
+            '\\' => {
+                quoted = true;
+                text.extend(chars.next());
+            }
+            c => text.push(c),
+        }
+    }
+    (text, quoted)
+}
+
+/// the length of the longest [`Operator`] `text` starts with
+fn operator_length(text: &str) -> Option<usize> {
+    (1..=3)
+        .rev()
+        .filter(|&length| text.is_char_boundary(length))
+        .find(|&length| text[..length].parse::<Operator>().is_ok())
+}
+
+/// true if `text` starts with `((`, `<(` or `>(`, which begin a word rather than an operator
+fn starts_with_substitution(text: &str) -> bool {
+    ["((", "<(", ">("]
+        .iter()
+        .any(|prefix| text.starts_with(prefix))
+}
+
+/// the number of digits `text` starts with, if a redirection operator follows them
+fn io_number_length(text: &str) -> Option<usize> {
+    let length = text.find(|c: char| !c.is_ascii_digit())?;
+    (length > 0 && text[length..].starts_with(['<', '>'])).then_some(length)
+}
+
+/// the index just past the word starting at `start`
+fn word_end(input: &str, start: usize) -> usize {
+    if input[start..].starts_with("((") {
+        return balanced_end(input, start, b'(', b')');
+    }
+    let bytes = input.as_bytes();
+    let mut index = start;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' => index += 2,
+            b'"' | b'\'' | b'`' => index = quoted_end(input, index),
+            b'$' => index = expansion_end(input, index + 1),
+            b'<' | b'>' if index == start && input[index + 1..].starts_with('(') => {
+                index = balanced_end(input, index + 1, b'(', b')');
+            }
+            byte if is_shell_blank(byte as char) => break,
+            byte if byte.is_ascii() && operator_length(&input[index..]).is_some() => break,
+            _ => index += 1,
+        }
+    }
+    index.min(bytes.len())
+}
+
+/// The index just past the quote closing the one at `start`, or the end of `text` if it is never closed.
+/// Backquotes are handled the same way.
+fn quoted_end(text: &str, start: usize) -> usize {
+    let bytes = text.as_bytes();
+    let delimiter = bytes[start];
+    let mut index = start + 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            byte if byte == delimiter => return index + 1,
+            b'`' if delimiter == b'"' => index = quoted_end(text, index),
+            b'\\' if delimiter != b'\'' => index += 2,
+            b'$' if delimiter == b'"' => index = expansion_end(text, index + 1),
+            _ => index += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// The index just past the `close` matching the `open` at `start`, or the end of `text` if it is never closed.
+///
+/// Skips over quotes, escapes and nested expansions while looking for it.
+fn balanced_end(text: &str, start: usize, open: u8, close: u8) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut index = start;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'"' | b'\'' | b'`' => index = quoted_end(text, index),
+            b'\\' => index += 2,
+            b'$' => index = expansion_end(text, index + 1),
+            byte if byte == open => {
+                depth += 1;
+                index += 1;
+            }
+            byte if byte == close => {
+                depth -= 1;
+                index += 1;
+                if depth == 0 {
+                    return index;
+                }
+            }
+            _ => index += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// after a `$` ending just before `index`, the end of the `{...}` or `(...)` following it, if there is one
+fn expansion_end(text: &str, index: usize) -> usize {
+    match text.as_bytes().get(index) {
+        Some(b'{') => balanced_end(text, index, b'{', b'}'),
+        Some(b'(') => balanced_end(text, index, b'(', b')'),
+        _ => index,
+    }
+}
+
+/// "The shell treats several parameters specially. These parameters may only be referenced;
+/// assignment to them is not allowed."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Special-Parameters)
+const SPECIAL_PARAMETERS: &str = "@*#?-$!";
+
+impl Word {
+    /// Splits `text`, which starts at byte `offset` of the input line, into its parts.
+    pub fn parse(text: &str, offset: usize) -> Self {
+        Self::parse_in(text, offset, false)
+    }
+
+    /// Like [`Word::parse`], for the body of a here-document.
+    ///
+    /// As within double quotes, only expansions are recognized, but a backslash does not escape `"`,
+    /// and a backslash followed by a newline removes both.
+    fn parse_here_document(text: &str, offset: usize) -> Self {
+        let mut word = Self::parse_in(text, offset, true);
+        for part in &mut word.parts {
+            match &mut part.kind {
+                WordPartKind::Escaped('"') => part.kind = WordPartKind::Literal("\\\"".to_string()),
+                WordPartKind::Literal(literal) => *literal = literal.replace("\\\n", ""),
+                _ => {}
+            }
+        }
+        word
+    }
+
+    /// like [`Word::parse`], for a word that appears within double quotes
+    fn parse_in(text: &str, offset: usize, in_double_quotes: bool) -> Self {
+        Self {
+            text: text.to_string(),
+            span: offset..offset + text.len(),
+            parts: parse_parts(text, offset, in_double_quotes),
+        }
+    }
+}
+
+fn parse_parts(text: &str, offset: usize, in_double_quotes: bool) -> Vec<WordPart> {
+    let bytes = text.as_bytes();
+    let mut parts = Vec::new();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        let start = index;
+        let kind = match bytes[index] {
+            b'\'' if !in_double_quotes => {
+                index = quoted_end(text, index);
+                match closed_inner(&text[start..index], '\'') {
+                    Some(inner) => WordPartKind::SingleQuoted(inner.to_string()),
+                    None => WordPartKind::Literal(text[start..index].to_string()),
+                }
+            }
+            b'"' if !in_double_quotes => {
+                index = quoted_end(text, index);
+                match closed_inner(&text[start..index], '"') {
+                    Some(inner) => {
+                        WordPartKind::DoubleQuoted(parse_parts(inner, offset + start + 1, true))
+                    }
+                    None => WordPartKind::Literal(text[start..index].to_string()),
+                }
+            }
+            b'\\' => {
+                let Some(escaped) = text[start + 1..].chars().next() else {
+                    break;
+                };
+                index += 1 + escaped.len_utf8();
+                match escaped {
+                    '$' | '\\' | '"' | '`' if in_double_quotes => WordPartKind::Escaped(escaped),
+                    _ if in_double_quotes => WordPartKind::Literal(text[start..index].to_string()),
+                    _ => WordPartKind::Escaped(escaped),
+                }
+            }
+            b'`' => {
+                index = quoted_end(text, index);
+                match closed_inner(&text[start..index], '`') {
+                    Some(inner) => WordPartKind::CommandSubstitution(unescape_backquoted(
+                        inner,
+                        in_double_quotes,
+                    )),
+                    None => WordPartKind::Literal(text[start..index].to_string()),
+                }
+            }
+            b'$' => {
+                let kind;
+                (kind, index) = parse_dollar(text, start, offset, in_double_quotes);
+                kind
+            }
+            b'<' | b'>' if start == 0 && !in_double_quotes && text[1..].starts_with('(') => {
+                index = balanced_end(text, 1, b'(', b')');
+                let direction = if bytes[start] == b'<' {
+                    ProcessDirection::Read
+                } else {
+                    ProcessDirection::Write
+                };
+                let parenthesized = &text[1..index];
+                match parenthesized
+                    .strip_prefix('(')
+                    .and_then(|p| p.strip_suffix(')'))
+                {
+                    Some(source) => {
+                        WordPartKind::ProcessSubstitution(direction, source.to_string())
+                    }
+                    None => WordPartKind::Literal(text[start..index].to_string()),
+                }
+            }
+            _ => {
+                let c = text[start..]
+                    .chars()
+                    .next()
+                    .expect("index is before the end");
+                index += c.len_utf8();
+                WordPartKind::Literal(c.to_string())
+            }
+        };
+        push_part(&mut parts, kind, offset + start..offset + index);
+    }
+    parts
+}
+
+/// adds `kind` to `parts`, joining adjacent literal text into a single part
+fn push_part(parts: &mut Vec<WordPart>, kind: WordPartKind, span: Span) {
+    if let (
+        Some(WordPart {
+            kind: WordPartKind::Literal(previous),
+            span: previous_span,
+        }),
+        WordPartKind::Literal(text),
+    ) = (parts.last_mut(), &kind)
+    {
+        previous.push_str(text);
+        previous_span.end = span.end;
+        return;
+    }
+    parts.push(WordPart { kind, span });
+}
+
+/// the text between the quotes of `quoted`, or `None` if it is missing its closing quote
+fn closed_inner(quoted: &str, quote: char) -> Option<&str> {
+    quoted
+        .strip_prefix(quote)
+        .and_then(|rest| rest.strip_suffix(quote))
+}
+
+/// Parses the expansion introduced by the `$` at `start`, returning it with the index just past it.
+///
+/// A `$` that does not start an expansion is literal.
+fn parse_dollar(
+    text: &str,
+    start: usize,
+    offset: usize,
+    in_double_quotes: bool,
+) -> (WordPartKind, usize) {
+    let after = start + 1;
+    let kind = match text[after..].chars().next() {
+        Some('{') => {
+            let end = balanced_end(text, after, b'{', b'}');
+            let braced = &text[start..end];
+            let parameter = braced
+                .strip_prefix("${")
+                .and_then(|b| b.strip_suffix('}'))
+                .and_then(|inner| parse_parameter(inner, offset + after + 1, in_double_quotes));
+            let kind = match parameter {
+                Some(parameter) => WordPartKind::Parameter(parameter),
+                None => WordPartKind::BadSubstitution(braced.to_string()),
+            };
+            return (kind, end);
+        }
+        Some('(') => {
+            let end = balanced_end(text, after, b'(', b')');
+            let parenthesized = &text[after..end];
+            let kind = match parenthesized
+                .strip_prefix('(')
+                .and_then(|p| p.strip_suffix(')'))
+            {
+                Some(source) => match source.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+                    Some(expression) => {
+                        WordPartKind::Arithmetic(Word::parse(expression, offset + after + 2))
+                    }
+                    None => WordPartKind::CommandSubstitution(source.to_string()),
+                },
+                None => WordPartKind::BadSubstitution(text[start..end].to_string()),
+            };
+            return (kind, end);
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            let length = text[after..]
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(text.len() - after);
+            return (
+                WordPartKind::Parameter(ParameterExpansion {
+                    name: text[after..after + length].to_string(),
+                    operation: None,
+                }),
+                after + length,
+            );
+        }
+        // without braces, only a single digit makes up a positional parameter
+        Some(c) if c.is_ascii_digit() || SPECIAL_PARAMETERS.contains(c) => {
+            WordPartKind::Parameter(ParameterExpansion {
+                name: c.to_string(),
+                operation: None,
+            })
+        }
+        _ => return (WordPartKind::Literal("$".to_string()), after),
+    };
+    (kind, after + 1)
+}
+
+/// splits the contents of `${...}` into the name of a parameter and the operation following it
+fn split_parameter_name(inner: &str) -> (&str, &str) {
+    let name_length = match inner.chars().next() {
+        Some(c) if c.is_ascii_digit() => inner
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(inner.len()),
+        Some(c) if SPECIAL_PARAMETERS.contains(c) => 1,
+        _ => inner
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(inner.len()),
+    };
+    inner.split_at(name_length)
+}
+
+/// Parses the contents of `${...}`, which start at byte `offset` of the input line.
+///
+/// `None` if they are not a valid parameter expansion.
+fn parse_parameter(
+    inner: &str,
+    offset: usize,
+    in_double_quotes: bool,
+) -> Option<ParameterExpansion> {
+    // `${#}` itself is the number of positional parameters
+    if let Some((name, "")) = inner
+        .strip_prefix('#')
+        .filter(|rest| !rest.is_empty())
+        .map(split_parameter_name)
+    {
+        return (!name.is_empty()).then(|| ParameterExpansion {
+            name: name.to_string(),
+            operation: Some(ParameterOperation::Length),
+        });
+    }
+
+    let (name, operation) = split_parameter_name(inner);
+    if name.is_empty() {
+        return None;
+    }
+    let parameter = |operation| {
+        Some(ParameterExpansion {
+            name: name.to_string(),
+            operation,
+        })
+    };
+    if operation.is_empty() {
+        return parameter(None);
+    }
+    // a part of `inner`, as a word of its own
+    let word = |part: &str, in_double_quotes: bool| {
+        let part_offset = part.as_ptr() as usize - inner.as_ptr() as usize;
+        Word::parse_in(part, offset + part_offset, in_double_quotes)
+    };
+
+    // `:-`, `:=`, `:?` and `:+` treat a null value like an unset one
+    let (null_is_unset, operation) = match operation.strip_prefix(':') {
+        Some(rest) if rest.starts_with(['-', '=', '?', '+']) => (true, rest),
+        _ => (false, operation),
+    };
+    let operator = operation.chars().next().expect("operation is not empty");
+    let rest = &operation[operator.len_utf8()..];
+    let conditional = |operator| {
+        // the word is expanded in the same context as the parameter
+        Some(ParameterOperation::Conditional {
+            operator,
+            null_is_unset,
+            word: word(rest, in_double_quotes),
+        })
+    };
+
+    let operation = match operator {
+        '-' => conditional(ConditionalOperator::UseDefault),
+        '=' => conditional(ConditionalOperator::AssignDefault),
+        '?' => conditional(ConditionalOperator::ErrorIfUnset),
+        '+' => conditional(ConditionalOperator::UseAlternative),
+        '#' | '%' => {
+            let longest = rest.starts_with(operator);
+            let pattern = word(if longest { &rest[1..] } else { rest }, false);
+            Some(if operator == '#' {
+                ParameterOperation::RemovePrefix { longest, pattern }
+            } else {
+                ParameterOperation::RemoveSuffix { longest, pattern }
+            })
+        }
+        '/' => {
+            let (mode, rest) = match rest.chars().next() {
+                Some('/') => (ReplaceMode::All, &rest[1..]),
+                Some('#') => (ReplaceMode::Prefix, &rest[1..]),
+                Some('%') => (ReplaceMode::Suffix, &rest[1..]),
+                _ => (ReplaceMode::First, rest),
+            };
+            let (pattern, replacement) = split_replacement(rest);
+            Some(ParameterOperation::Replace {
+                mode,
+                pattern: word(pattern, false),
+                replacement: replacement.map(|replacement| word(replacement, in_double_quotes)),
+            })
+        }
+        ':' => {
+            let (offset, length) = match rest.split_once(':') {
+                Some((offset, length)) => (offset, Some(length)),
+                None => (rest, None),
+            };
+            Some(ParameterOperation::Substring {
+                offset: word(offset, false),
+                length: length.map(|length| word(length, false)),
+            })
+        }
+        _ => None,
+    };
+    parameter(Some(operation?))
+}
+
+/// splits `pattern/replacement` at the first `/` that is not quoted, escaped or part of an expansion
+fn split_replacement(word: &str) -> (&str, Option<&str>) {
+    let bytes = word.as_bytes();
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'/' => return (&word[..index], Some(&word[index + 1..])),
+            b'"' | b'\'' | b'`' => index = quoted_end(word, index),
+            b'\\' => index += 2,
+            b'$' => index = expansion_end(word, index + 1),
+            _ => index += 1,
+        }
+    }
+    (word, None)
+}
+
+/// "When the old-style backquote form of substitution is used,
+/// backslash retains its literal meaning except when followed by ‘$’, ‘`’, or ‘\’."
+fn unescape_backquoted(inner: &str, in_double_quotes: bool) -> String {
+    let mut source = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            source.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(escaped @ ('$' | '`' | '\\')) => source.push(escaped),
+            Some('"') if in_double_quotes => source.push('"'),
+            Some(other) => source.extend(['\\', other]),
+            None => source.push('\\'),
+        }
+    }
+    source
+}
@@ -1,18 +1,33 @@
+mod arithmetic;
+mod ast;
+mod brace;
 mod builtin_commands;
 mod commands;
 mod completion;
 mod executable_path;
+mod executor;
+mod expansion;
+mod glob;
+mod jobs;
+mod lexer;
+mod parser;
+mod pattern;
 mod stream_target;
 mod tokens;
+mod variables;
 
-use crate::builtin_commands::history_default_path;
-use crate::commands::{Command, CommandStream};
+use crate::builtin_commands::{history_default_path, ExitRequest};
+use crate::commands::exit_code;
+use crate::parser::Parser;
+use crate::stream_target::{InStream, OutStream};
+use crate::variables::VARIABLES;
 use anyhow::Result as AnyResult;
 use builtin_commands::BuiltinCommand;
 use completion::MyCompleter;
 use rustyline::{
     config::Configurer, error::ReadlineError, history::FileHistory, CompletionType, Editor,
 };
+use std::io::{stderr, stdout};
 use std::sync::{LazyLock, RwLock};
 
 pub static EDITOR: LazyLock<RwLock<Editor<MyCompleter, FileHistory>>> =
@@ -22,35 +37,109 @@ fn main() -> AnyResult<()> {
     #[cfg(debug_assertions)] // logging setup
     init_logging();
 
+    let mut args = std::env::args().skip(1);
+    if let Some(script_path) = args.next() {
+        return run_script(&script_path, args.collect());
+    }
+
+    VARIABLES.write().unwrap().interactive = true;
+    if let Err(e) = jobs::enable_job_control() {
+        log::warn!("failed to enable job control: {e}");
+    }
+
     loop {
-        let raw_line = readline_adding_history()?;
+        jobs::report_finished_jobs(std::io::stderr())?;
+        let Some(raw_line) = readline_adding_history()? else {
+            // end of input behaves like the `exit` builtin
+            BuiltinCommand::Exit.run_with(
+                &[],
+                InStream::Std,
+                OutStream::Std(stdout()),
+                OutStream::Std(stderr()),
+            )?;
+            unreachable!("the exit builtin returned");
+        };
 
-        let command_stream = CommandStream::from(&raw_line);
+        let parser = Parser::from(&raw_line);
 
-        for command_construction_result in command_stream {
-            let pipeline = match command_construction_result {
+        for parse_result in parser {
+            let list = match parse_result {
                 Err(e) => {
                     log::warn!("received error: {e:?}");
                     eprintln!("{e}");
                     break;
                 }
-                Ok(command) => command,
+                Ok(list) => list,
             };
-            pipeline.run_blocking()?;
+            list.execute()?;
         }
     }
 }
 
-fn readline_adding_history() -> AnyResult<String> {
+/// Runs the commands in the file at `path` with `arguments` as the positional parameters,
+/// then exits with the status of the last command.
+fn run_script(path: &str, arguments: Vec<String>) -> AnyResult<()> {
+    let script = match std::fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("{path}: {e}");
+            std::process::exit(127);
+        }
+    };
+    let mut variables = VARIABLES.write().unwrap();
+    variables.shell_name = path.to_string();
+    variables.positional_parameters = arguments;
+    drop(variables);
+
+    for parse_result in Parser::from(&script) {
+        let list = match parse_result {
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                std::process::exit(2);
+            }
+            Ok(list) => list,
+        };
+        if let Err(e) = list.execute() {
+            // such as `${NAME:?message}`, which ends a script
+            match e.get_ref().and_then(|e| e.downcast_ref::<ExitRequest>()) {
+                Some(ExitRequest(code)) => std::process::exit(*code),
+                None => return Err(e.into()),
+            }
+        }
+    }
+    std::process::exit(exit_code(VARIABLES.read().unwrap().last_exit_status))
+}
+
+/// `None` once the end of input is reached (Ctrl-D on an empty line)
+///
+/// Keeps reading lines while the command is incomplete, such as up to the delimiter of a here-document.
+fn readline_adding_history() -> AnyResult<Option<String>> {
     let mut editor = EDITOR.write().unwrap();
-    let raw_line = match editor.readline("$ ") {
+    let mut raw_line = match editor.readline("$ ") {
         Ok(line) => line,
-        Err(ReadlineError::Interrupted | ReadlineError::Eof) => todo!(),
+        // Ctrl-C discards the line, the prompt gets drawn again
+        Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+        Err(ReadlineError::Eof) => return Ok(None),
         Err(err) => return Err(err.into()),
     };
+    while Parser::from(&raw_line)
+        .find_map(Result::err)
+        .is_some_and(|e| e.is_incomplete())
+    {
+        match editor.readline("> ") {
+            Ok(line) => {
+                raw_line.push('\n');
+                raw_line.push_str(&line);
+            }
+            Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+            // the parser reports what is missing
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
     editor.add_history_entry(&raw_line)?;
     drop(editor);
-    Ok(raw_line)
+    Ok(Some(raw_line))
 }
 
 fn setup_rustyline_editor() -> Result<Editor<MyCompleter, FileHistory>, anyhow::Error> {
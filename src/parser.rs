@@ -0,0 +1,463 @@
+use crate::ast::{
+    AndOrList, ArithmeticForLoop, Assignment, Command, CompoundCommand, ForLoop, IfClause, List,
+    ListItem, LogicalOperator, Pipeline, Redirect, SimpleCommand, WhileLoop,
+};
+use crate::lexer::TokenStream;
+use crate::tokens::{ControlOperator, Operator, ReservedWord, Token, Word, WordPart, WordPartKind};
+use crate::variables::is_valid_name;
+use std::iter::Peekable;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ParseError {
+    #[error("syntax error near unexpected token `{0}'")]
+    UnexpectedToken(String),
+    #[error("syntax error: unexpected end of file")]
+    UnexpectedEof,
+    #[error("here-document delimited by end-of-file (wanted `{0}')")]
+    UnterminatedHereDocument(String),
+}
+
+impl ParseError {
+    /// true if more lines of input could complete the command
+    pub const fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            Self::UnexpectedEof | Self::UnterminatedHereDocument(_)
+        )
+    }
+}
+
+impl From<Token> for ParseError {
+    fn from(token: Token) -> Self {
+        match token {
+            Token::Operator(Operator::Control(ControlOperator::Newline)) => {
+                Self::UnexpectedToken("newline".to_string())
+            }
+            other => Self::UnexpectedToken(other.to_string()),
+        }
+    }
+}
+
+/// Builds the [syntax tree][crate::ast] out of a [`TokenStream`], one complete command at a time.
+///
+/// A complete command is a [`List`] terminated by a newline or by the end of input.
+pub struct Parser<'a> {
+    tokens: Peekable<TokenStream<'a>>,
+}
+
+impl<'a, T: AsRef<str>> From<&'a T> for Parser<'a> {
+    fn from(value: &'a T) -> Self {
+        Self {
+            tokens: TokenStream::from(value).peekable(),
+        }
+    }
+}
+
+impl Iterator for Parser<'_> {
+    type Item = Result<List, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.complete_command().transpose()
+    }
+}
+
+impl Parser<'_> {
+    fn complete_command(&mut self) -> Result<Option<List>, ParseError> {
+        self.skip_newlines();
+        if self.tokens.peek().is_none() {
+            return Ok(None);
+        }
+
+        let list = self.list()?;
+        match self.tokens.next() {
+            None | Some(Token::Operator(Operator::Control(ControlOperator::Newline))) => {
+                Ok(Some(list))
+            }
+            Some(unexpected) => Err(unexpected.into()),
+        }
+    }
+
+    /// `and_or ((';' | '&') and_or)* [';' | '&']`
+    fn list(&mut self) -> Result<List, ParseError> {
+        let mut items = Vec::new();
+        loop {
+            let and_or = self.and_or()?;
+            let asynchronous = self.next_if_control(ControlOperator::Ampersand).is_some();
+            let separated =
+                asynchronous || self.next_if_control(ControlOperator::Semicolon).is_some();
+            items.push(ListItem {
+                and_or,
+                asynchronous,
+            });
+
+            if !separated || self.at_list_end() {
+                break;
+            }
+        }
+        Ok(List { items })
+    }
+
+    fn and_or(&mut self) -> Result<AndOrList, ParseError> {
+        let head = self.pipeline()?;
+        let mut tail = Vec::new();
+        loop {
+            let operator = if self.next_if_control(ControlOperator::And).is_some() {
+                LogicalOperator::And
+            } else if self.next_if_control(ControlOperator::Or).is_some() {
+                LogicalOperator::Or
+            } else {
+                break;
+            };
+            self.skip_newlines();
+            tail.push((operator, self.pipeline()?));
+        }
+        Ok(AndOrList { head, tail })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negated = self
+            .tokens
+            .next_if(|token| {
+                matches!(token, Token::Word(word) if matches!(word.reserved_word(), Some(ReservedWord::Bang)))
+            })
+            .is_some();
+
+        let mut commands = vec![self.command()?];
+        let mut pipes_stderr = vec![false];
+        loop {
+            let pipes = if self.next_if_control(ControlOperator::Pipe).is_some() {
+                false
+            } else if self.next_if_control(ControlOperator::PipeAmp).is_some() {
+                true
+            } else {
+                break;
+            };
+            *pipes_stderr
+                .last_mut()
+                .expect("there is a command before the pipe") = pipes;
+            self.skip_newlines();
+            commands.push(self.command()?);
+            pipes_stderr.push(false);
+        }
+        Ok(Pipeline {
+            negated,
+            commands,
+            pipes_stderr,
+        })
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        if let Some(expression) = self.tokens.peek().and_then(as_arithmetic_command) {
+            self.tokens.next();
+            return Ok(Command::Arithmetic(expression));
+        }
+        let compound_command = match self.peek_reserved_word() {
+            Some(ReservedWord::If) => CompoundCommand::If(self.if_clause()?),
+            Some(ReservedWord::While | ReservedWord::Until) => {
+                CompoundCommand::While(self.while_loop()?)
+            }
+            Some(ReservedWord::For) => self.for_loop()?,
+            // only valid as part of a compound command
+            Some(
+                ReservedWord::Then
+                | ReservedWord::Elif
+                | ReservedWord::Else
+                | ReservedWord::Fi
+                | ReservedWord::Do
+                | ReservedWord::Done
+                | ReservedWord::In,
+            ) => return Err(self.tokens.next().expect("peeked a reserved word").into()),
+            _ => return Ok(Command::Simple(self.simple_command()?)),
+        };
+
+        let mut redirects = Vec::new();
+        while matches!(
+            self.tokens.peek(),
+            Some(Token::Operator(Operator::Redirect(_)) | Token::IoNumber(_))
+        ) {
+            redirects.push(self.redirect()?);
+        }
+        Ok(Command::Compound(compound_command, redirects))
+    }
+
+    /// `if compound_list then compound_list (elif compound_list then compound_list)* [else compound_list] fi`
+    fn if_clause(&mut self) -> Result<IfClause, ParseError> {
+        self.expect_reserved_word(ReservedWord::If)?;
+        let mut branches = Vec::new();
+        let mut else_branch = None;
+        loop {
+            let condition = self.compound_list()?;
+            self.expect_reserved_word(ReservedWord::Then)?;
+            branches.push((condition, self.compound_list()?));
+            match self.peek_reserved_word() {
+                Some(ReservedWord::Elif) => {
+                    self.tokens.next();
+                }
+                Some(ReservedWord::Else) => {
+                    self.tokens.next();
+                    else_branch = Some(self.compound_list()?);
+                    break;
+                }
+                _ => break,
+            }
+        }
+        self.expect_reserved_word(ReservedWord::Fi)?;
+        Ok(IfClause {
+            branches,
+            else_branch,
+        })
+    }
+
+    /// `(while | until) compound_list do_group`
+    fn while_loop(&mut self) -> Result<WhileLoop, ParseError> {
+        let until = self.peek_reserved_word() == Some(ReservedWord::Until);
+        self.tokens.next();
+        let condition = self.compound_list()?;
+        let body = self.do_group()?;
+        Ok(WhileLoop {
+            until,
+            condition,
+            body,
+        })
+    }
+
+    /// `for name [in word*] (';' | newline) do_group`, or `for (( init; condition; update )) [';'] do_group`
+    fn for_loop(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.expect_reserved_word(ReservedWord::For)?;
+        if let Some(expression) = self.tokens.peek().and_then(as_arithmetic_command) {
+            let token = self.tokens.next().expect("peeked an arithmetic command");
+            let [init, condition, update] = split_arithmetic_for(&expression).ok_or(token)?;
+            self.next_if_control(ControlOperator::Semicolon);
+            self.skip_newlines();
+            return Ok(CompoundCommand::ArithmeticFor(ArithmeticForLoop {
+                init,
+                condition,
+                update,
+                body: self.do_group()?,
+            }));
+        }
+
+        let name = match self.tokens.next() {
+            Some(Token::Word(word)) => word.text,
+            Some(unexpected) => return Err(unexpected.into()),
+            None => return Err(ParseError::UnexpectedEof),
+        };
+        self.skip_newlines();
+        let words = if self.peek_reserved_word() == Some(ReservedWord::In) {
+            self.tokens.next();
+            let mut words = Vec::new();
+            while let Some(Token::Word(word)) =
+                self.tokens.next_if(|token| matches!(token, Token::Word(_)))
+            {
+                words.push(word);
+            }
+            let separated = self.next_if_control(ControlOperator::Semicolon).is_some()
+                || self.next_if_control(ControlOperator::Newline).is_some();
+            if !separated {
+                return Err(match self.tokens.next() {
+                    Some(unexpected) => unexpected.into(),
+                    None => ParseError::UnexpectedEof,
+                });
+            }
+            Some(words)
+        } else {
+            self.next_if_control(ControlOperator::Semicolon);
+            None
+        };
+        self.skip_newlines();
+        Ok(CompoundCommand::For(ForLoop {
+            name,
+            words,
+            body: self.do_group()?,
+        }))
+    }
+
+    /// `do compound_list done`
+    fn do_group(&mut self) -> Result<List, ParseError> {
+        self.expect_reserved_word(ReservedWord::Do)?;
+        let body = self.compound_list()?;
+        self.expect_reserved_word(ReservedWord::Done)?;
+        Ok(body)
+    }
+
+    /// A list within a compound command, in which newlines separate commands like `;` does.
+    ///
+    /// Ends before the reserved word that continues the compound command.
+    fn compound_list(&mut self) -> Result<List, ParseError> {
+        self.skip_newlines();
+        let mut items = Vec::new();
+        loop {
+            let and_or = self.and_or()?;
+            let asynchronous = self.next_if_control(ControlOperator::Ampersand).is_some();
+            let separated = asynchronous
+                || self.next_if_control(ControlOperator::Semicolon).is_some()
+                || self.next_if_control(ControlOperator::Newline).is_some();
+            self.skip_newlines();
+            items.push(ListItem {
+                and_or,
+                asynchronous,
+            });
+
+            let continues_compound_command = matches!(
+                self.peek_reserved_word(),
+                Some(
+                    ReservedWord::Then
+                        | ReservedWord::Elif
+                        | ReservedWord::Else
+                        | ReservedWord::Fi
+                        | ReservedWord::Do
+                        | ReservedWord::Done
+                )
+            );
+            if !separated || continues_compound_command || self.tokens.peek().is_none() {
+                break;
+            }
+        }
+        Ok(List { items })
+    }
+
+    /// the reserved word next, if the next token is one
+    fn peek_reserved_word(&mut self) -> Option<ReservedWord> {
+        match self.tokens.peek() {
+            Some(Token::Word(word)) => word.reserved_word(),
+            _ => None,
+        }
+    }
+
+    fn expect_reserved_word(&mut self, expected: ReservedWord) -> Result<(), ParseError> {
+        match self.tokens.next() {
+            Some(Token::Word(word)) if word.reserved_word() == Some(expected) => Ok(()),
+            Some(unexpected) => Err(unexpected.into()),
+            None => Err(ParseError::UnexpectedEof),
+        }
+    }
+
+    fn simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+
+        loop {
+            match self.tokens.peek() {
+                Some(Token::Word(_)) => {
+                    let Some(Token::Word(word)) = self.tokens.next() else {
+                        unreachable!("peeked to confirm is a word")
+                    };
+                    match as_assignment(&word) {
+                        Some(assignment) if words.is_empty() => assignments.push(assignment),
+                        _ => words.push(word),
+                    }
+                }
+                // redirections may come anywhere, even before the command name
+                Some(Token::Operator(Operator::Redirect(_)) | Token::IoNumber(_)) => {
+                    redirects.push(self.redirect()?);
+                }
+                _ => break,
+            }
+        }
+
+        if words.is_empty() && assignments.is_empty() && redirects.is_empty() {
+            return match self.tokens.next() {
+                Some(unexpected) => Err(unexpected.into()),
+                None => Err(ParseError::UnexpectedEof),
+            };
+        }
+
+        Ok(SimpleCommand {
+            assignments,
+            words,
+            redirects,
+        })
+    }
+
+    fn redirect(&mut self) -> Result<Redirect, ParseError> {
+        let io_number = match self
+            .tokens
+            .next_if(|token| matches!(token, Token::IoNumber(_)))
+        {
+            Some(Token::IoNumber(fd)) => Some(fd),
+            _ => None,
+        };
+        let Some(Token::Operator(Operator::Redirect(operator))) = self.tokens.next() else {
+            unreachable!("the lexer only produces a number followed by a redirect operator")
+        };
+        match self.tokens.next() {
+            Some(Token::Word(target)) => Ok(Redirect {
+                io_number,
+                operator,
+                target,
+                here_document: None,
+            }),
+            Some(Token::HereDocument(here_document)) if !here_document.terminated => Err(
+                ParseError::UnterminatedHereDocument(here_document.delimiter.text),
+            ),
+            Some(Token::HereDocument(here_document)) => Ok(Redirect {
+                io_number,
+                operator,
+                target: here_document.delimiter,
+                here_document: Some(here_document.body),
+            }),
+            Some(unexpected) => Err(unexpected.into()),
+            None => Err(ParseError::UnexpectedToken("newline".to_string())),
+        }
+    }
+
+    fn next_if_control(&mut self, control: ControlOperator) -> Option<Token> {
+        self.tokens.next_if(
+            |token| matches!(token, Token::Operator(Operator::Control(c)) if *c == control),
+        )
+    }
+
+    /// true if no further command follows in the current complete command
+    fn at_list_end(&mut self) -> bool {
+        matches!(
+            self.tokens.peek(),
+            None | Some(Token::Operator(Operator::Control(ControlOperator::Newline)))
+        )
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.next_if_control(ControlOperator::Newline).is_some() {}
+    }
+}
+
+/// the expression of a `(( expression ))` command, which the lexer keeps together as one word
+fn as_arithmetic_command(token: &Token) -> Option<Word> {
+    let Token::Word(word) = token else {
+        return None;
+    };
+    let expression = word.text.strip_prefix("((")?.strip_suffix("))")?;
+    Some(Word::parse(expression, word.span.start + 2))
+}
+
+/// the three expressions of `for (( init; condition; update ))`, `None` unless there are exactly three
+fn split_arithmetic_for(expression: &Word) -> Option<[Word; 3]> {
+    let mut start = expression.span.start;
+    let expressions = expression
+        .text
+        .split(';')
+        .map(|text| {
+            let word = Word::parse(text, start);
+            start += text.len() + 1;
+            word
+        })
+        .collect::<Vec<_>>();
+    expressions.try_into().ok()
+}
+
+/// `Some` if `word` has the form `NAME=value`, with `NAME` unquoted
+fn as_assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart {
+        kind: WordPartKind::Literal(literal),
+        ..
+    }) = word.parts.first()
+    else {
+        return None;
+    };
+    let (name, _) = literal.split_once('=')?;
+    let value_start = name.len() + 1;
+    is_valid_name(name).then(|| Assignment {
+        name: name.to_string(),
+        value: Word::parse(&word.text[value_start..], word.span.start + value_start),
+    })
+}
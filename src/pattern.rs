@@ -0,0 +1,274 @@
+//! "The special pattern characters must be quoted if they are to be matched literally."
+//!
+//! -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Pattern-Matching)
+
+/// A parsed shell pattern, made of `*`, `?`, bracket expressions and literal characters.
+///
+/// A backslash makes the following character literal.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    items: Vec<PatternItem>,
+}
+
+#[derive(Debug, Clone)]
+enum PatternItem {
+    Literal(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyString,
+    /// `[...]`
+    Bracket {
+        negated: bool,
+        members: Vec<BracketMember>,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum BracketMember {
+    Char(char),
+    Range(char, char),
+    /// `[:name:]`
+    Class(String),
+}
+
+impl BracketMember {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Self::Char(member) => *member == c,
+            Self::Range(start, end) => (*start..=*end).contains(&c),
+            Self::Class(class) => match class.as_str() {
+                "alnum" => c.is_alphanumeric(),
+                "alpha" => c.is_alphabetic(),
+                "ascii" => c.is_ascii(),
+                "blank" => c == ' ' || c == '\t',
+                "cntrl" => c.is_control(),
+                "digit" => c.is_ascii_digit(),
+                "graph" => c.is_ascii_graphic(),
+                "lower" => c.is_lowercase(),
+                "print" => c.is_ascii_graphic() || c == ' ',
+                "punct" => c.is_ascii_punctuation(),
+                "space" => c.is_whitespace(),
+                "upper" => c.is_uppercase(),
+                "word" => c.is_alphanumeric() || c == '_',
+                "xdigit" => c.is_ascii_hexdigit(),
+                _ => false,
+            },
+        }
+    }
+}
+
+impl PatternItem {
+    /// true if the item matches `c` on its own; never for `*`, which matches any number of characters
+    fn matches_char(&self, c: char) -> bool {
+        match self {
+            Self::AnyString => false,
+            Self::AnyChar => true,
+            Self::Literal(literal) => *literal == c,
+            Self::Bracket { negated, members } => {
+                members.iter().any(|member| member.contains(c)) != *negated
+            }
+        }
+    }
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut items = Vec::new();
+        let mut index = 0;
+
+        while index < chars.len() {
+            let item = match chars[index] {
+                '*' => PatternItem::AnyString,
+                '?' => PatternItem::AnyChar,
+                '[' => match parse_bracket(&chars[index + 1..]) {
+                    Some((item, length)) => {
+                        index += length;
+                        item
+                    }
+                    None => PatternItem::Literal('['),
+                },
+                '\\' if index + 1 < chars.len() => {
+                    index += 1;
+                    PatternItem::Literal(chars[index])
+                }
+                c => PatternItem::Literal(c),
+            };
+            items.push(item);
+            index += 1;
+        }
+        Self { items }
+    }
+
+    /// true if the whole of `text` matches
+    pub fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        matches_from(&self.items, &text)
+    }
+}
+
+/// `chars` starts after the opening `[`.
+/// Returns the item and the number of chars used, including the closing `]`
+fn parse_bracket(chars: &[char]) -> Option<(PatternItem, usize)> {
+    let mut index = 0;
+    let negated = matches!(chars.first(), Some('!' | '^'));
+    if negated {
+        index += 1;
+    }
+
+    let mut members = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(index)?;
+        match c {
+            ']' if !first => break,
+            '[' if chars.get(index + 1) == Some(&':') => {
+                let name = &chars[index + 2..];
+                let class_length = name.windows(2).position(|pair| pair == [':', ']'])?;
+                members.push(BracketMember::Class(name[..class_length].iter().collect()));
+                index += class_length + 4;
+            }
+            _ => {
+                let c = if c == '\\' {
+                    index += 1;
+                    *chars.get(index)?
+                } else {
+                    c
+                };
+                match (chars.get(index + 1), chars.get(index + 2)) {
+                    (Some('-'), Some(&end)) if end != ']' => {
+                        members.push(BracketMember::Range(c, end));
+                        index += 3;
+                    }
+                    _ => {
+                        members.push(BracketMember::Char(c));
+                        index += 1;
+                    }
+                }
+            }
+        }
+        first = false;
+    }
+    Some((PatternItem::Bracket { negated, members }, index + 1))
+}
+
+/// Matches with a single backtracking point: on a mismatch, the latest `*` takes one more character.
+///
+/// An earlier `*` never needs to take more, as anything it could skip the latest one can skip as well,
+/// so the time taken is at most the product of the lengths rather than exponential.
+fn matches_from(items: &[PatternItem], text: &[char]) -> bool {
+    let (mut item_index, mut text_index) = (0, 0);
+    // the item following the latest `*`, and where the text it took so far ends
+    let mut backtrack = None;
+    while text_index < text.len() {
+        match items.get(item_index) {
+            Some(PatternItem::AnyString) => {
+                item_index += 1;
+                backtrack = Some((item_index, text_index));
+                continue;
+            }
+            Some(item) if item.matches_char(text[text_index]) => {
+                item_index += 1;
+                text_index += 1;
+                continue;
+            }
+            _ => {}
+        }
+        let Some((after_star, star_end)) = backtrack else {
+            return false;
+        };
+        backtrack = Some((after_star, star_end + 1));
+        item_index = after_star;
+        text_index = star_end + 1;
+    }
+    items[item_index..]
+        .iter()
+        .all(|item| matches!(item, PatternItem::AnyString))
+}
+
+/// escapes every character with a special meaning in a pattern, so that it only matches itself
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// true if `pattern` has an unescaped `*`, `?` or `[`
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => _ = chars.next(),
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// the text a pattern without wildcards matches, with its escapes removed
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stars_give_up_on_a_long_text_without_backtracking_exponentially() {
+        let text = "a".repeat(10_000);
+        assert!(!Pattern::parse("*a*a*a*a*a*b").matches(&text));
+        assert!(!Pattern::parse("*a*b").matches(&text));
+        assert!(Pattern::parse("*a*b").matches(&(text + "b")));
+    }
+
+    #[test]
+    fn class_after_a_multibyte_char() {
+        let pattern = Pattern::parse("é[[:alpha:]]");
+        assert!(pattern.matches("éx"));
+        assert!(pattern.matches("éß"));
+        assert!(!pattern.matches("é1"));
+        assert!(Pattern::parse("[é[:digit:]]").matches("7"));
+    }
+
+    #[test]
+    fn negated_range() {
+        let pattern = Pattern::parse("[!a-c]");
+        assert!(!pattern.matches("a"));
+        assert!(!pattern.matches("c"));
+        assert!(pattern.matches("d"));
+        assert!(pattern.matches("-"));
+    }
+
+    #[test]
+    fn escaped_star_is_literal() {
+        let pattern = Pattern::parse(r"a\*");
+        assert!(pattern.matches("a*"));
+        assert!(!pattern.matches("ab"));
+        assert_eq!(escape("*"), r"\*");
+    }
+
+    #[test]
+    fn unterminated_bracket_is_literal() {
+        let pattern = Pattern::parse("[ab");
+        assert!(pattern.matches("[ab"));
+        assert!(!pattern.matches("a"));
+        assert!(Pattern::parse("x[").matches("x["));
+        assert!(!Pattern::parse("[[:alpha:").matches("a"));
+    }
+}
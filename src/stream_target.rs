@@ -1,7 +1,20 @@
+use crate::commands::spawn_subshell;
+use crate::jobs::JOBS;
+use nix::errno::Errno;
+use nix::libc::STDIN_FILENO;
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, stderr, stdin, stdout, Read, Stderr, Stdout, Write};
 use std::ops::{Deref, DerefMut};
-use std::process::Stdio;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd, RawFd};
+use std::process::{ExitStatus, Stdio};
+use std::sync::{LazyLock, Mutex};
+
+/// The descriptors above 2 the shell keeps open across commands, such as fd 3 after `exec 3>log`.
+///
+/// Each file sits at whatever descriptor it was opened at, and is closed on exec.
+/// External commands get it at the number it is keyed by instead.
+pub static SHELL_FDS: LazyLock<Mutex<BTreeMap<RawFd, File>>> = LazyLock::new(Mutex::default);
 
 /// simple wrapper over the implementation
 #[derive(Debug)]
@@ -20,6 +33,54 @@ pub fn pipe() -> (PipeReader, PipeWriter) {
     (PipeReader { inner: reader }, PipeWriter { inner: writer })
 }
 
+/// The input of a here-document or here-string: a pipe that `content` is written to.
+///
+/// Content that might not fit in the pipe is written from a subshell of its own,
+/// so the command gets to read it meanwhile.
+pub fn here_document(content: String) -> io::Result<File> {
+    // the capacity of a pipe is at least a page
+    const FITS_IN_PIPE: usize = 4096;
+
+    let (reader, mut writer) = pipe();
+    if content.len() <= FITS_IN_PIPE {
+        writer.write_all(content.as_bytes())?;
+    } else {
+        let writing = spawn_subshell(None, vec![writer.as_raw_fd()], || {
+            // the command may exit without reading everything
+            let _ = writer.write_all(content.as_bytes());
+            Ok(ExitStatus::default())
+        })?;
+        // reaped once it finishes, as nothing waits for it
+        JOBS.lock().unwrap().detach(writing);
+    }
+    Ok(OwnedFd::from(reader).into())
+}
+
+impl From<PipeReader> for OwnedFd {
+    fn from(reader: PipeReader) -> Self {
+        reader.inner.into()
+    }
+}
+
+impl From<PipeWriter> for OwnedFd {
+    fn from(writer: PipeWriter) -> Self {
+        writer.inner.into()
+    }
+}
+
+impl DerefMut for PipeReader {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+impl Deref for PipeReader {
+    type Target = imp::PipeReader;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
 impl DerefMut for PipeWriter {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.inner
@@ -39,6 +100,57 @@ pub enum InStream {
     Std,
     File(File),
     PipeReader(PipeReader),
+    /// closed with `<&-`
+    Closed,
+}
+
+impl InStream {
+    /// another handle to the same stream
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Self::Std => Self::Std,
+            Self::File(f) => Self::File(f.try_clone()?),
+            Self::PipeReader(r) => Self::PipeReader(PipeReader {
+                inner: r.inner.try_clone()?,
+            }),
+            Self::Closed => Self::Closed,
+        })
+    }
+
+    /// a new file descriptor for the stream, so that it can stand in for another one
+    pub fn try_clone_fd(&self) -> io::Result<OwnedFd> {
+        match self {
+            Self::Std => stdin().as_fd().try_clone_to_owned(),
+            Self::File(f) => f.as_fd().try_clone_to_owned(),
+            Self::PipeReader(r) => r.inner.as_fd().try_clone_to_owned(),
+            Self::Closed => Err(Errno::EBADF.into()),
+        }
+    }
+
+    pub const fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed)
+    }
+
+    /// the descriptor the stream reads from, `None` for the shell's own standard input or a closed stream
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            Self::File(f) => Some(f.as_raw_fd()),
+            Self::PipeReader(r) => Some(r.inner.as_raw_fd()),
+            Self::Std | Self::Closed => None,
+        }
+    }
+}
+
+impl Read for InStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            // unbuffered, so whatever the shell does not read is left for the commands it runs
+            Self::Std => Ok(nix::unistd::read(STDIN_FILENO, buf)?),
+            Self::File(f) => f.read(buf),
+            Self::PipeReader(r) => r.read(buf),
+            Self::Closed => Err(Errno::EBADF.into()),
+        }
+    }
 }
 
 mod imp {
@@ -55,6 +167,72 @@ pub enum OutStream<T> {
     Std(T),
     File(File),
     PipeWriter(PipeWriter),
+    /// closed with `>&-`
+    Closed,
+}
+
+impl<T> OutStream<T> {
+    fn try_clone_or(&self, std: impl FnOnce() -> T) -> io::Result<Self> {
+        Ok(match self {
+            Self::Std(_) => Self::Std(std()),
+            Self::File(f) => Self::File(f.try_clone()?),
+            Self::PipeWriter(w) => Self::PipeWriter(PipeWriter {
+                inner: w.inner.try_clone()?,
+            }),
+            Self::Closed => Self::Closed,
+        })
+    }
+
+    pub const fn is_closed(&self) -> bool {
+        matches!(self, Self::Closed)
+    }
+}
+
+impl<T: AsFd> OutStream<T> {
+    /// another handle to the same stream, to stand in for a different one as in `2>&1`
+    pub fn try_clone_as<U>(&self) -> io::Result<OutStream<U>> {
+        Ok(match self {
+            Self::Std(t) => OutStream::File(t.as_fd().try_clone_to_owned()?.into()),
+            Self::File(f) => OutStream::File(f.try_clone()?),
+            Self::PipeWriter(w) => OutStream::PipeWriter(PipeWriter {
+                inner: w.inner.try_clone()?,
+            }),
+            Self::Closed => OutStream::Closed,
+        })
+    }
+
+    /// the descriptor the stream writes to, `None` for the shell's own standard stream or a closed stream
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            Self::File(f) => Some(f.as_raw_fd()),
+            Self::PipeWriter(w) => Some(w.inner.as_raw_fd()),
+            Self::Std(_) | Self::Closed => None,
+        }
+    }
+
+    /// a new file descriptor for the stream, so that it can stand in for another one
+    pub fn try_clone_fd(&self) -> io::Result<OwnedFd> {
+        match self {
+            Self::Std(t) => t.as_fd().try_clone_to_owned(),
+            Self::File(f) => f.as_fd().try_clone_to_owned(),
+            Self::PipeWriter(w) => w.inner.as_fd().try_clone_to_owned(),
+            Self::Closed => Err(Errno::EBADF.into()),
+        }
+    }
+}
+
+impl OutStream<Stdout> {
+    /// another handle to the same stream
+    pub fn try_clone(&self) -> io::Result<Self> {
+        self.try_clone_or(stdout)
+    }
+}
+
+impl OutStream<Stderr> {
+    /// another handle to the same stream
+    pub fn try_clone(&self) -> io::Result<Self> {
+        self.try_clone_or(stderr)
+    }
 }
 
 impl<W: Write> Write for OutStream<W> {
@@ -63,6 +241,7 @@ impl<W: Write> Write for OutStream<W> {
             Self::Std(t) => t.write(buf),
             Self::File(f) => f.write(buf),
             Self::PipeWriter(w) => w.write(buf),
+            Self::Closed => Err(Errno::EBADF.into()),
         }
     }
     fn flush(&mut self) -> std::io::Result<()> {
@@ -70,6 +249,7 @@ impl<W: Write> Write for OutStream<W> {
             Self::Std(t) => t.flush(),
             Self::File(f) => f.flush(),
             Self::PipeWriter(w) => w.flush(),
+            Self::Closed => Ok(()),
         }
     }
 }
@@ -80,6 +260,8 @@ impl From<InStream> for Stdio {
             InStream::Std => Self::inherit(),
             InStream::File(f) => f.into(),
             InStream::PipeReader(w) => w.inner.into(),
+            // the descriptor itself is closed just before the command executes
+            InStream::Closed => Self::null(),
         }
     }
 }
@@ -90,6 +272,8 @@ impl<T> From<OutStream<T>> for Stdio {
             OutStream::Std(_) => Self::inherit(),
             OutStream::File(f) => f.into(),
             OutStream::PipeWriter(w) => w.inner.into(),
+            // the descriptor itself is closed just before the command executes
+            OutStream::Closed => Self::null(),
         }
     }
 }
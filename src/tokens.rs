@@ -1,30 +1,78 @@
 use my_derives::{FromInnerType, MayStartWith, MyFromStrParse, ZDisplay};
 use std::borrow::Borrow;
 use std::ffi::OsStr;
-use std::fmt::Display;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+use std::os::fd::RawFd;
 use std::path::PathBuf;
 use strum::{AsRefStr, EnumString, IntoStaticStr};
 
+/// "Before a command is executed, its input and output may be redirected using a special notation
+/// interpreted by the shell."
+///
+/// Each operator may be preceded by the number of the file descriptor it redirects, see [`Token::IoNumber`].
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Redirections)
 #[derive(Debug, Clone, Copy, IntoStaticStr, EnumString, MayStartWith, ZDisplay, AsRefStr)]
 pub enum RedirectOperator {
     #[strum(serialize = "<")]
-    RStdin,
-    #[strum(serialize = ">", serialize = "1>")]
-    RStdout,
-    #[strum(serialize = "2>")]
-    RStderr,
-    #[strum(serialize = ">>", serialize = "1>>")]
-    AppendStdout,
-    #[strum(serialize = "2>>")]
-    AppendStderr,
+    Input,
+    #[strum(serialize = ">")]
+    Output,
+    #[strum(serialize = ">>")]
+    Append,
+    /// like [`Self::Output`], even if `noclobber` is set
+    #[strum(serialize = ">|")]
+    Clobber,
+    /// opens the file for both reading and writing
+    #[strum(serialize = "<>")]
+    ReadWrite,
+    /// `<&n` makes the descriptor a copy of the input descriptor `n`, `<&-` closes it
+    #[strum(serialize = "<&")]
+    DuplicateInput,
+    /// `>&n` makes the descriptor a copy of the output descriptor `n`, `>&-` closes it
+    #[strum(serialize = ">&")]
+    DuplicateOutput,
+    /// redirects both stdout and stderr
+    #[strum(serialize = "&>")]
+    OutputAndError,
+    #[strum(serialize = "&>>")]
+    AppendOutputAndError,
+    /// the lines following the command, up to a line made of the delimiter, see [`HereDocument`]
+    #[strum(serialize = "<<")]
+    HereDocument,
+    /// like [`Self::HereDocument`], with leading tabs removed from every line
+    #[strum(serialize = "<<-")]
+    HereDocumentStripTabs,
+    /// `<<< word`: the expanded word followed by a newline
+    #[strum(serialize = "<<<")]
+    HereString,
 }
 
 impl RedirectOperator {
     #[inline]
     // passing by value because it is cheap and Self implements copy
-    /// true iff `AppendStdout | AppendStderr`
+    /// true iff the operator appends to its file rather than overwriting it
     pub const fn appends(self) -> bool {
-        matches!(self, Self::AppendStdout | Self::AppendStderr)
+        matches!(self, Self::Append | Self::AppendOutputAndError)
+    }
+
+    /// the file descriptor redirected when no number precedes the operator
+    pub const fn default_fd(self) -> RawFd {
+        match self {
+            Self::Input
+            | Self::ReadWrite
+            | Self::DuplicateInput
+            | Self::HereDocument
+            | Self::HereDocumentStripTabs
+            | Self::HereString => 0,
+            Self::Output
+            | Self::Append
+            | Self::Clobber
+            | Self::DuplicateOutput
+            | Self::OutputAndError
+            | Self::AppendOutputAndError => 1,
+        }
     }
 }
 
@@ -34,7 +82,18 @@ pub fn is_shell_blank(c: impl Borrow<char>) -> bool {
     [' ', '\t'].contains(c.borrow())
 }
 
-#[derive(Debug, Clone, Copy, MyFromStrParse, MayStartWith, IntoStaticStr, ZDisplay, AsRefStr)]
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    MyFromStrParse,
+    MayStartWith,
+    IntoStaticStr,
+    ZDisplay,
+    AsRefStr,
+)]
 pub enum ControlOperator {
     #[strum(serialize = "\n")]
     Newline,
@@ -71,14 +130,32 @@ pub enum ControlOperator {
 pub enum Token {
     Word(Word),
     Operator(Operator),
+    /// "digits [...] immediately followed by the character '<' or '>'": the descriptor a redirection applies to
+    ///
+    /// -- [POSIX](https://pubs.opengroup.org/onlinepubs/9799919799/utilities/V3_chap02.html#tag_19_10_01)
+    IoNumber(RawFd),
+    /// the delimiter following `<<` or `<<-`, with the lines read for it
+    HereDocument(HereDocument),
 }
 
-impl AsRef<str> for Token {
-    fn as_ref(&self) -> &str {
-        match self {
-            Self::Word(w) => w.as_ref(),
-            Self::Operator(o) => o.as_ref(),
-        }
+/// "This type of redirection instructs the shell to read input from the current source until a line containing only
+/// word (with no trailing blanks) is seen."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Here-Documents)
+#[derive(Debug, Clone)]
+pub struct HereDocument {
+    pub delimiter: Word,
+    /// The lines up to the delimiter, each ending with a newline.
+    ///
+    /// Only parsed for expansions if no part of the delimiter is quoted, otherwise a single literal.
+    pub body: Word,
+    /// false if the input ended before a line made of the delimiter
+    pub terminated: bool,
+}
+
+impl Display for HereDocument {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.delimiter)
     }
 }
 
@@ -101,7 +178,7 @@ impl TryFrom<Token> for PathBuf {
     /// ```
     fn try_from(token: Token) -> Result<Self, Self::Error> {
         match token {
-            Token::Word(Word::SimpleWord(word)) => Ok(word.into()),
+            Token::Word(word) if word.reserved_word().is_none() => Ok(word.text.into()),
             _reserved => Err(()),
         }
     }
@@ -116,12 +193,6 @@ impl From<String> for Token {
     }
 }
 
-impl AsRef<OsStr> for Token {
-    fn as_ref(&self) -> &OsStr {
-        <Self as AsRef<str>>::as_ref(self).as_ref()
-    }
-}
-
 impl Token {
     pub const fn is_command_delimiter(&self) -> bool {
         use ControlOperator as CO;
@@ -140,16 +211,133 @@ impl Token {
     }
 }
 
+/// byte offsets into the line of input a [`Word`] was read from
+pub type Span = Range<usize>;
+
 /// "A sequence of characters treated as a unit by the shell. Words may not include unquoted metacharacters."
 ///
+/// Keeps the text as typed, split into [`WordPart`]s that tell how each of its pieces is quoted or expanded.
+///
 /// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-word)
 #[derive(Debug, Clone)]
-pub enum Word {
+pub struct Word {
+    /// the word as it was typed, quotes and escapes included
+    pub text: String,
+    pub span: Span,
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
     /// "A word that has a special meaning to the shell. Most reserved words introduce shell flow control constructs, such as for and while."
     ///
+    /// Only an unquoted word can be one.
+    ///
     /// -- [ref](https://www.gnu.org/software/bash/manual/bash.html#index-reserved-word)
-    ReservedWord(ReservedWord),
-    SimpleWord(String),
+    pub fn reserved_word(&self) -> Option<ReservedWord> {
+        match self.parts.as_slice() {
+            [WordPart {
+                kind: WordPartKind::Literal(text),
+                ..
+            }] => text.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WordPart {
+    pub kind: WordPartKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum WordPartKind {
+    /// text without a special meaning: unquoted, or inside [double quotes][Self::DoubleQuoted]
+    Literal(String),
+    /// `\c`: a backslash quoting the character following it
+    Escaped(char),
+    /// `'...'`
+    SingleQuoted(String),
+    /// `"..."`: the parts within, of which only expansions keep their special meaning
+    DoubleQuoted(Vec<WordPart>),
+    /// `$NAME`, `${NAME}` or `${NAME` followed by an operation `}`
+    Parameter(ParameterExpansion),
+    /// `$(...)` or `` `...` ``, holding the source of the commands to run
+    CommandSubstitution(String),
+    /// `$((...))`, holding the expression, which is expanded before it is evaluated
+    Arithmetic(Word),
+    /// `<(...)` or `>(...)` starting a word, holding the source of the commands to run
+    ProcessSubstitution(ProcessDirection, String),
+    /// a `${...}` that is not a valid parameter expansion, only reported once it is expanded
+    BadSubstitution(String),
+}
+
+/// which way data flows through the path a process substitution expands to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessDirection {
+    /// `<(...)`: reading the path gives the output of the commands
+    Read,
+    /// `>(...)`: writing to the path gives the commands their input
+    Write,
+}
+
+/// "The basic form of parameter expansion is ${parameter}."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#Shell-Parameter-Expansion)
+#[derive(Debug, Clone)]
+pub struct ParameterExpansion {
+    /// a variable name, the number of a positional parameter or a special parameter
+    pub name: String,
+    pub operation: Option<ParameterOperation>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ParameterOperation {
+    /// `${#NAME}`
+    Length,
+    /// `${NAME-word}` and the like; with a `:` before the operator, a null value counts as unset
+    Conditional {
+        operator: ConditionalOperator,
+        null_is_unset: bool,
+        word: Word,
+    },
+    /// `${NAME#pattern}`, or `${NAME##pattern}` for the longest match
+    RemovePrefix { longest: bool, pattern: Word },
+    /// `${NAME%pattern}`, or `${NAME%%pattern}` for the longest match
+    RemoveSuffix { longest: bool, pattern: Word },
+    /// `${NAME/pattern/replacement}`
+    Replace {
+        mode: ReplaceMode,
+        pattern: Word,
+        replacement: Option<Word>,
+    },
+    /// `${NAME:offset}` and `${NAME:offset:length}`, both arithmetic expressions
+    Substring { offset: Word, length: Option<Word> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConditionalOperator {
+    /// `-`
+    UseDefault,
+    /// `=`
+    AssignDefault,
+    /// `?`
+    ErrorIfUnset,
+    /// `+`
+    UseAlternative,
+}
+
+/// which matches of the pattern `${NAME/pattern/replacement}` replaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceMode {
+    /// `/`: the first one
+    First,
+    /// `//`: every one
+    All,
+    /// `/#`: one at the start of the value
+    Prefix,
+    /// `/%`: one at the end of the value
+    Suffix,
 }
 
 impl AsRef<OsStr> for Word {
@@ -160,52 +348,56 @@ impl AsRef<OsStr> for Word {
 
 impl AsRef<str> for Word {
     fn as_ref(&self) -> &str {
-        match self {
-            Self::ReservedWord(r) => r.as_ref(),
-            Self::SimpleWord(s) => s,
-        }
+        &self.text
     }
 }
 
 impl From<String> for Word {
     fn from(value: String) -> Self {
-        match value.parse() {
-            Ok(rw) => Self::ReservedWord(rw),
-            Err(()) => Self::SimpleWord(value),
-        }
+        Self::parse(&value, 0)
     }
 }
 
 impl From<&str> for Word {
     fn from(value: &str) -> Self {
-        match value.parse() {
-            Ok(rw) => Self::ReservedWord(rw),
-            Err(()) => Self::SimpleWord(value.to_string()),
-        }
+        Self::parse(value, 0)
     }
 }
 
 impl Display for Word {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                Self::ReservedWord(reserved_word) => <&str>::from(reserved_word).to_string(),
-                Self::SimpleWord(w) => w.to_owned(),
-            }
-        )
+        write!(f, "{}", self.text)
     }
 }
 
-#[derive(Debug, Clone, MyFromStrParse, IntoStaticStr, AsRefStr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, MyFromStrParse, IntoStaticStr, AsRefStr)]
 pub enum ReservedWord {
     #[strum(serialize = "if")]
     If,
     #[strum(serialize = "then")]
     Then,
+    #[strum(serialize = "elif")]
+    Elif,
+    #[strum(serialize = "else")]
+    Else,
+    #[strum(serialize = "fi")]
+    Fi,
+    #[strum(serialize = "while")]
+    While,
+    #[strum(serialize = "until")]
+    Until,
+    #[strum(serialize = "for")]
+    For,
+    #[strum(serialize = "in")]
+    In,
+    #[strum(serialize = "do")]
+    Do,
+    #[strum(serialize = "done")]
+    Done,
     #[strum(serialize = "time")]
     Time,
+    #[strum(serialize = "!")]
+    Bang,
     // todo add more reserved words
 }
 
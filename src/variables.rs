@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::process::ExitStatus;
+use std::sync::{LazyLock, RwLock};
+
+/// every shell variable, initialised from the environment the shell was started with
+pub static VARIABLES: LazyLock<RwLock<VariableTable>> =
+    LazyLock::new(|| RwLock::new(VariableTable::from_environment()));
+
+/// "A parameter denoted by a name."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-variable)
+#[derive(Debug, Clone, Default)]
+pub struct Variable {
+    /// `None` for variables that were declared (e.g. by `export NAME`) but never assigned
+    pub value: Option<String>,
+    /// exported variables are passed on to the environment of child processes
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VariableError {
+    #[error("{0}: readonly variable")]
+    Readonly(String),
+    #[error("`{0}': not a valid identifier")]
+    InvalidName(String),
+}
+
+/// The shell's parameters: its variables together with the positional and special parameters.
+#[derive(Debug, Default)]
+pub struct VariableTable {
+    variables: BTreeMap<String, Variable>,
+    /// `$0`
+    pub shell_name: String,
+    /// `$$`, the process id of the shell, which its subshells expand it to as well
+    pub shell_pid: u32,
+    /// `$1`, `$2`, ...
+    pub positional_parameters: Vec<String>,
+    /// `$?`, the status of the most recently executed foreground pipeline
+    pub last_exit_status: ExitStatus,
+    /// `set -C`: `>` refuses to overwrite an existing regular file, `>|` still does
+    pub noclobber: bool,
+    /// `true` when reading commands at the prompt rather than from a script
+    pub interactive: bool,
+}
+
+/// "A word consisting solely of letters, numbers, and underscores, and beginning with a letter or underscore."
+///
+/// -- [ref manual](https://www.gnu.org/software/bash/manual/bash.html#index-name)
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl VariableTable {
+    fn from_environment() -> Self {
+        let mut variables: BTreeMap<String, Variable> = std::env::vars()
+            .map(|(name, value)| {
+                let variable = Variable {
+                    value: Some(value),
+                    exported: true,
+                    readonly: false,
+                };
+                (name, variable)
+            })
+            .collect();
+
+        if let Ok(current_dir) = std::env::current_dir() {
+            let pwd = variables.entry("PWD".to_string()).or_insert(Variable {
+                exported: true,
+                ..Variable::default()
+            });
+            pwd.value = Some(current_dir.to_string_lossy().into_owned());
+        }
+        Self {
+            variables,
+            shell_name: std::env::args().next().unwrap_or_default(),
+            shell_pid: std::process::id(),
+            ..Self::default()
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name)?.value.as_deref()
+    }
+
+    /// assigns `value`, keeping the attributes of an existing variable
+    pub fn set(&mut self, name: &str, value: impl Into<String>) -> Result<(), VariableError> {
+        self.declare(name, Some(value.into()))?;
+        Ok(())
+    }
+
+    /// Makes sure a variable called `name` exists, assigning `value` if it is `Some`.
+    ///
+    /// The returned reference allows changing its attributes.
+    pub fn declare(
+        &mut self,
+        name: &str,
+        value: Option<String>,
+    ) -> Result<&mut Variable, VariableError> {
+        if !is_valid_name(name) {
+            return Err(VariableError::InvalidName(name.to_string()));
+        }
+        let variable = self.variables.entry(name.to_string()).or_default();
+        if value.is_some() {
+            if variable.readonly {
+                return Err(VariableError::Readonly(name.to_string()));
+            }
+            variable.value = value;
+        }
+        Ok(variable)
+    }
+
+    /// Assigns `assignments` until the returned variables are passed to [`Self::restore`],
+    /// as assignments preceding a builtin only last while it runs.
+    pub fn assign_temporarily(
+        &mut self,
+        assignments: &[(String, String)],
+    ) -> Result<Vec<(String, Option<Variable>)>, VariableError> {
+        let mut saved = Vec::with_capacity(assignments.len());
+        for (name, value) in assignments {
+            let previous = self.variables.get(name).cloned();
+            if let Err(e) = self.set(name, value.as_str()) {
+                self.restore(saved);
+                return Err(e);
+            }
+            saved.push((name.clone(), previous));
+        }
+        Ok(saved)
+    }
+
+    /// puts back the variables [`Self::assign_temporarily`] replaced
+    pub fn restore(&mut self, saved: Vec<(String, Option<Variable>)>) {
+        // in reverse, so a name assigned twice gets its original variable back
+        for (name, previous) in saved.into_iter().rev() {
+            match previous {
+                Some(variable) => self.variables.insert(name, variable),
+                None => self.variables.remove(&name),
+            };
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), VariableError> {
+        if !is_valid_name(name) {
+            return Err(VariableError::InvalidName(name.to_string()));
+        }
+        if self.variables.get(name).is_some_and(|v| v.readonly) {
+            return Err(VariableError::Readonly(name.to_string()));
+        }
+        self.variables.remove(name);
+        Ok(())
+    }
+
+    /// name and value of every exported variable that has a value, for the environment of a child
+    pub fn exported(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.variables.iter().filter_map(|(name, variable)| {
+            variable
+                .exported
+                .then_some((name.as_str(), variable.value.as_deref()?))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Variable)> {
+        self.variables
+            .iter()
+            .map(|(name, variable)| (name.as_str(), variable))
+    }
+
+    /// a `declare` command that would recreate the variable, as printed by `declare -p`
+    pub fn declaration(&self, name: &str) -> Option<String> {
+        let variable = self.variables.get(name)?;
+        let mut flags = String::new();
+        if variable.readonly {
+            flags.push('r');
+        }
+        if variable.exported {
+            flags.push('x');
+        }
+        if flags.is_empty() {
+            flags.push('-');
+        }
+
+        let mut declaration = format!("declare -{flags} {name}");
+        if let Some(value) = &variable.value {
+            let escaped: String = value
+                .chars()
+                .flat_map(|c| match c {
+                    '"' | '\\' | '$' | '`' => vec!['\\', c],
+                    _ => vec![c],
+                })
+                .collect();
+            write!(declaration, "=\"{escaped}\"").expect("writing to a string");
+        }
+        Some(declaration)
+    }
+}